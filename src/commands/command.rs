@@ -1,18 +1,42 @@
 use std::path::PathBuf;
+use crate::github::poller::MaintenanceWindow;
+use crate::utils::detect::parse_app;
+use crate::utils::hooks::HookKind;
+use crate::utils::output::OutputFormat;
 use structopt::StructOpt;
+use structopt::clap::Shell;
 
 #[derive(Debug, StructOpt)]
 pub enum Command {
+    /// Add a server
     #[structopt(name = "add")]
     Add {
         #[structopt(short="n", long)]
         name: String,
 
-        #[structopt(short="f", long, default_value = "flask")]
-        framework: String,
+        /// Detected from the project if not given, flask otherwise
+        #[structopt(short="f", long)]
+        framework: Option<String>,
 
-        #[structopt(short="d", long, parse(from_os_str))]
-        path: PathBuf,
+        /// Entry point as module:callable, detected from the project if not given
+        #[structopt(short="a", long, parse(try_from_str = parse_app))]
+        app: Option<String>,
+
+        /// Server directory, or where to clone `--git` (defaults to workspace/<name> of the state directory)
+        #[structopt(short="d", long, parse(from_os_str), required_unless = "git")]
+        path: Option<PathBuf>,
+
+        /// Repository to clone the server from
+        #[structopt(short="g", long)]
+        git: Option<String>,
+
+        /// Branch, tag or commit checked out after cloning
+        #[structopt(short="r", long = "ref", requires = "git")]
+        rev: Option<String>,
+
+        /// Install dependencies and run the build pipeline after adding
+        #[structopt(short="i", long)]
+        install: bool,
 
         #[structopt(short="w", long, default_value = "4")]
         workers: u32,
@@ -27,51 +51,168 @@ pub enum Command {
         timeout: u32,
 
         #[structopt(short="l", long, parse(from_os_str))]
-        log_path: Option<PathBuf>
+        log_path: Option<PathBuf>,
+
+        #[structopt(short="e", long, parse(from_os_str))]
+        env_file: Option<PathBuf>,
+
+        #[structopt(short="v", long, parse(from_os_str))]
+        venv: Option<PathBuf>,
+
+        /// Tags used to select groups of servers, repeatable
+        #[structopt(long)]
+        tag: Vec<String>,
+
+        /// Servers that must be running before this one starts, repeatable
+        #[structopt(long)]
+        depends_on: Vec<String>
     },
 
+    /// Change the settings of a server
+    #[structopt(name = "edit")]
+    Edit {
+        #[structopt(short="n", long)]
+        name: String,
+
+        #[structopt(short="w", long)]
+        workers: Option<u32>,
+
+        #[structopt(short="b", long)]
+        bind: Option<String>,
+
+        #[structopt(short="p", long)]
+        port: Option<u32>,
+
+        #[structopt(short="t", long)]
+        timeout: Option<u32>,
+
+        #[structopt(short="l", long, parse(from_os_str))]
+        log_path: Option<PathBuf>,
+
+        #[structopt(short="e", long, parse(from_os_str))]
+        env_file: Option<PathBuf>,
+
+        /// Stop loading an env file
+        #[structopt(long, conflicts_with = "env-file")]
+        no_env_file: bool,
+
+        #[structopt(short="v", long, parse(from_os_str))]
+        venv: Option<PathBuf>,
+
+        /// Run the server with the Python found in the PATH again
+        #[structopt(long, conflicts_with = "venv")]
+        no_venv: bool,
+
+        #[structopt(short="a", long, parse(try_from_str = parse_app))]
+        app: Option<String>,
+
+        /// Detect the entry point from the project again
+        #[structopt(long, conflicts_with = "app")]
+        no_app: bool,
+
+        /// Tags added to the server, repeatable
+        #[structopt(long)]
+        tag: Vec<String>,
+
+        /// Tags removed from the server, repeatable
+        #[structopt(long)]
+        untag: Vec<String>,
+
+        /// Dependencies added to the server, repeatable
+        #[structopt(long)]
+        depends_on: Vec<String>,
+
+        /// Dependencies removed from the server, repeatable
+        #[structopt(long)]
+        drop_depends_on: Vec<String>,
+
+        #[structopt(short="r", long)]
+        restart: bool
+    },
+
+    /// Stop and remove a server
     #[structopt(name = "remove")]
     Remove {
         #[structopt(short="n", long)]
         name: String,
     },
 
+    /// Start servers
     #[structopt(name = "start")]
     Start {
-        #[structopt(short="n", long)]
-        name: String,
+        #[structopt(flatten)]
+        target: Target,
     },
 
+    /// Stop servers
     #[structopt(name = "stop")]
     Stop {
-        #[structopt(short="n", long)]
-        name: String,
+        #[structopt(flatten)]
+        target: Target,
     },
 
+    /// Restart servers
     #[structopt(name = "restart")]
     Restart {
-        #[structopt(short="n", long)]
-        name: String,
+        #[structopt(flatten)]
+        target: Target,
+    },
+
+    /// Reload servers in place
+    #[structopt(name = "reload")]
+    Reload {
+        #[structopt(flatten)]
+        target: Target,
     },
 
+    /// Pull new commits of git servers and apply them
     #[structopt(name = "update")]
     Update {
+        #[structopt(flatten)]
+        target: Target,
+    },
+
+    /// Deploy a branch, tag or commit
+    #[structopt(name = "deploy")]
+    Deploy {
+        #[structopt(short="n", long)]
+        name: String,
+
+        /// Branch, tag or commit to deploy
+        #[structopt(short="r", long = "ref")]
+        rev: String,
+    },
+
+    /// Go back to the previously deployed commit
+    #[structopt(name = "rollback")]
+    Rollback {
         #[structopt(short="n", long)]
         name: String,
     },
 
+    /// Deploy into separate release directories
+    #[structopt(name = "releases")]
+    Releases(ReleasesCommand),
+
+    /// Switch between two instances on deploy
+    #[structopt(name = "blue_green")]
+    BlueGreen(BlueGreenCommand),
+
+    /// Show the logs of a server
     #[structopt(name = "monitor")]
     Monitor {
         #[structopt(short="n", long)]
         name: String,
     },
 
+    /// Empty the logs of a server
     #[structopt(name = "clear_logs")]
     ClearLogs {
         #[structopt(short="n", long)]
         name: String,
     },
 
+    /// Plot the access logs of a server
     #[structopt(name = "visualize")]
     Visualize {
         #[structopt(short="n", long)]
@@ -81,12 +222,14 @@ pub enum Command {
         show: bool
     },
 
+    /// Initialize a git repository in the server directory
     #[structopt(name = "git_init")]
     GitInit {
         #[structopt(short="n", long)]
         name: String,
     },
 
+    /// Set the origin remote of a server
     #[structopt(name = "add_origin")]
     AddOrigin {
         #[structopt(short="n", long)]
@@ -96,6 +239,36 @@ pub enum Command {
         remote_url: String
     },
 
+    /// Environment variables of a server
+    #[structopt(name = "env")]
+    Env(EnvCommand),
+
+    /// Build steps of a server
+    #[structopt(name = "build")]
+    Build(BuildCommand),
+
+    /// Commands run around start, stop and update
+    #[structopt(name = "hooks")]
+    Hooks(HooksCommand),
+
+    /// Runs manage.py of a Django server, e.g. `manage -n site -- createsuperuser`
+    #[structopt(name = "manage")]
+    Manage {
+        #[structopt(short="n", long)]
+        name: String,
+
+        #[structopt(last = true)]
+        args: Vec<String>,
+    },
+
+    /// Show the branch, local changes and incoming commits of a server
+    #[structopt(name = "git_status")]
+    GitStatus {
+        #[structopt(short="n", long)]
+        name: String,
+    },
+
+    /// Add a Redis server
     #[structopt(name = "redis")]
     Redis {
         #[structopt(short="d", long, parse(from_os_str))]
@@ -112,13 +285,350 @@ pub enum Command {
     },
 
 
+    /// Update servers on push webhooks
+    #[structopt(name = "webhook")]
+    Webhook {
+        /// Address to listen on, anything but loopback requires a secret
+        #[structopt(short="b", long, default_value = "127.0.0.1")]
+        bind: String,
+
+        #[structopt(short="p", long, default_value = "9000")]
+        port: u32,
+
+        /// Shared secret used to verify GitHub signatures or GitLab tokens
+        #[structopt(short="s", long)]
+        secret: Option<String>
+    },
+
+    /// Poll origins and update servers on new commits
+    #[structopt(name = "auto_update")]
+    AutoUpdate(AutoUpdateCommand),
+
+    /// Show hardware usage
     #[structopt(name = "hardware")]
-    Hardware, // Show hardware usage
+    Hardware,
 
+    /// List all servers
     #[structopt(name = "list")]
-    List, // List all servers
+    List,
 
+    /// Stop and remove every server
     #[structopt(name = "flush")]
-    Flush
+    Flush,
+
+    /// Saved states of the servers, one per change
+    #[structopt(name = "snapshots")]
+    Snapshots(SnapshotsCommand),
+
+    /// Change a setting of the prompt session
+    #[structopt(name = "set")]
+    Set(SetCommand),
+
+    /// Print a completion script for bash, zsh or fish
+    #[structopt(name = "completions")]
+    Completions {
+        #[structopt(possible_values = &["bash", "zsh", "fish"], required_unless = "servers")]
+        shell: Option<Shell>,
+        /// Print the server names, called back by the completion scripts
+        #[structopt(long, hidden = true)]
+        servers: bool,
+    },
+
+    /// Print the man page
+    #[structopt(name = "man")]
+    Man,
+
+}
+
+impl Command {
+    // Whether the command only reads the servers, so it can run next to an instance writing them
+    pub fn is_read_only(&self) -> bool {
+        matches!(self,
+            Command::List
+            | Command::Monitor { .. }
+            | Command::GitStatus { .. }
+            | Command::Hardware
+            | Command::Visualize { .. }
+            | Command::Env(EnvCommand::List { .. })
+            | Command::Build(BuildCommand::List { .. })
+            | Command::Hooks(HooksCommand::List { .. })
+            | Command::Releases(ReleasesCommand::List { .. })
+            | Command::Snapshots(SnapshotsCommand::List)
+            | Command::Snapshots(SnapshotsCommand::Restore { dry_run: true, .. })
+            | Command::Set(_)
+            | Command::Completions { .. }
+            | Command::Man
+        )
+    }
+
+    // Parses a line typed in the prompt, words are split like a shell does so quoted
+    // values such as `hooks set -c "make install"` stay whole
+    pub fn from_prompt(input: &str) -> Result<Command, String> {
+        let words = shell_words::split(input).map_err(|e| e.to_string())?;
+        let binary = std::env::args().next().unwrap_or_default();
+        Command::from_iter_safe(std::iter::once(binary).chain(words)).map_err(|e| e.to_string())
+    }
+}
+
+#[derive(Debug, StructOpt)]
+pub enum EnvCommand {
+    /// Set variables
+    #[structopt(name = "set")]
+    Set {
+        #[structopt(short="n", long)]
+        name: String,
+
+        /// Variables given as KEY=VALUE
+        vars: Vec<String>
+    },
+
+    /// Remove variables
+    #[structopt(name = "unset")]
+    Unset {
+        #[structopt(short="n", long)]
+        name: String,
+
+        keys: Vec<String>
+    },
+
+    /// List variables, values are masked unless --show is given
+    #[structopt(name = "list")]
+    List {
+        #[structopt(short="n", long)]
+        name: String,
+
+        #[structopt(short="s", long)]
+        show: bool
+    }
+}
+
+#[derive(Debug, StructOpt)]
+pub enum BuildCommand {
+    /// Append a build step
+    #[structopt(name = "add")]
+    Add {
+        #[structopt(short="n", long)]
+        name: String,
+
+        #[structopt(short="c", long)]
+        command: String,
+
+        /// Working directory relative to the server directory
+        #[structopt(short="d", long, parse(from_os_str))]
+        dir: Option<PathBuf>,
+
+        #[structopt(short="t", long)]
+        timeout: Option<u64>,
+
+        /// Glob patterns on changed files that trigger the step
+        #[structopt(short="p", long)]
+        pattern: Vec<String>,
+
+        /// Variables given as KEY=VALUE
+        #[structopt(short="e", long)]
+        env: Vec<String>
+    },
+
+    /// Remove a build step by index
+    #[structopt(name = "remove")]
+    Remove {
+        #[structopt(short="n", long)]
+        name: String,
+
+        #[structopt(short="i", long)]
+        index: usize
+    },
+
+    /// List the build steps
+    #[structopt(name = "list")]
+    List {
+        #[structopt(short="n", long)]
+        name: String,
+    },
+
+    /// Run every build step
+    #[structopt(name = "run")]
+    Run {
+        #[structopt(short="n", long)]
+        name: String,
+    }
+}
+
+#[derive(Debug, StructOpt)]
+pub enum ReleasesCommand {
+    /// Switch a git server to the releases layout
+    #[structopt(name = "enable")]
+    Enable {
+        #[structopt(short="n", long)]
+        name: String,
+
+        /// Directory holding the repository cache, the releases and the `current` symlink
+        #[structopt(short="d", long, parse(from_os_str))]
+        root: PathBuf,
+
+        #[structopt(short="k", long, default_value = "5")]
+        keep: u32
+    },
+
+    /// List the releases, the live one marked
+    #[structopt(name = "list")]
+    List {
+        #[structopt(short="n", long)]
+        name: String,
+    }
+}
+
+#[derive(Debug, StructOpt)]
+pub enum BlueGreenCommand {
+    /// Run the server as two instances on two ports
+    #[structopt(name = "enable")]
+    Enable {
+        #[structopt(short="n", long)]
+        name: String,
+
+        #[structopt(short="g", long)]
+        green_port: u32,
+
+        /// Proxy config symlink switched to the active color's upstream block
+        #[structopt(short="u", long, parse(from_os_str))]
+        upstream: Option<PathBuf>,
+
+        /// Command run after switching, e.g. "nginx -s reload"
+        #[structopt(short="r", long)]
+        proxy_reload: Option<String>
+    },
+
+    /// Go back to a single instance
+    #[structopt(name = "disable")]
+    Disable {
+        #[structopt(short="n", long)]
+        name: String,
+    },
+
+    /// Start the idle instance and move traffic to it
+    #[structopt(name = "switch")]
+    Switch {
+        #[structopt(short="n", long)]
+        name: String,
+    }
+}
+
+#[derive(Debug, StructOpt)]
+pub enum AutoUpdateCommand {
+    /// Poll the origin of a server
+    #[structopt(name = "enable")]
+    Enable {
+        #[structopt(short="n", long)]
+        name: String,
+
+        /// Seconds between two polls
+        #[structopt(short="i", long, default_value = "300")]
+        interval: u64,
+
+        /// Only update during this UTC window, e.g. 02:00-04:00
+        #[structopt(short="w", long)]
+        window: Option<MaintenanceWindow>
+    },
+
+    /// Stop polling the origin of a server
+    #[structopt(name = "disable")]
+    Disable {
+        #[structopt(short="n", long)]
+        name: String,
+    },
+
+    /// Poll every server once
+    #[structopt(name = "check")]
+    Check,
+
+    /// Keep polling every server at its interval
+    #[structopt(name = "run")]
+    Run
+}
+
+#[derive(Debug, StructOpt)]
+pub enum HooksCommand {
+    /// Set a hook
+    #[structopt(name = "set")]
+    Set {
+        #[structopt(short="n", long)]
+        name: String,
+
+        /// One of pre_start, post_start, pre_stop, post_stop, pre_update, post_update
+        hook: HookKind,
+
+        #[structopt(short="c", long)]
+        command: String,
+
+        /// Seconds before the hook is killed, 60 by default
+        #[structopt(short="t", long)]
+        timeout: Option<u64>
+    },
+
+    /// Remove a hook
+    #[structopt(name = "unset")]
+    Unset {
+        #[structopt(short="n", long)]
+        name: String,
+
+        hook: HookKind
+    },
+
+    /// List the hooks of a server
+    #[structopt(name = "list")]
+    List {
+        #[structopt(short="n", long)]
+        name: String,
+    }
+}
+
+#[derive(Debug, StructOpt)]
+pub enum SnapshotsCommand {
+    /// List the saved snapshots
+    #[structopt(name = "list")]
+    List,
+
+    /// Shows the changes to the servers, then restores them as saved in the snapshot
+    #[structopt(name = "restore")]
+    Restore {
+        id: u64,
+
+        /// Only show the changes
+        #[structopt(long)]
+        dry_run: bool,
+
+        /// Restore without asking for confirmation
+        #[structopt(short="y", long)]
+        yes: bool,
+    },
+}
+
+#[derive(Debug, StructOpt)]
+pub enum SetCommand {
+    /// Print results as text or json
+    #[structopt(name = "output")]
+    Output {
+        #[structopt(possible_values = &["text", "json"])]
+        format: OutputFormat,
+    }
+}
+
+// Servers a bulk command applies to: a name or glob, the servers carrying every given tag, or all of them
+#[derive(Debug, StructOpt)]
+pub struct Target {
+    /// Server name, or a glob matching several
+    #[structopt(short="n", long, required_unless_one = &["tag", "all"])]
+    pub name: Option<String>,
+
+    /// Servers carrying this tag, repeatable
+    #[structopt(short="t", long)]
+    pub tag: Vec<String>,
+
+    /// Every server
+    #[structopt(short="a", long, conflicts_with_all = &["name", "tag"])]
+    pub all: bool,
 
-}
\ No newline at end of file
+    /// Servers handled at the same time
+    #[structopt(short="j", long, default_value = "4")]
+    pub parallel: usize,
+}
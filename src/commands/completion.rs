@@ -0,0 +1,214 @@
+use rustyline::completion::{Completer, FilenameCompleter, Pair};
+use rustyline::highlight::Highlighter;
+use rustyline::hint::Hinter;
+use rustyline::validate::Validator;
+use rustyline::{Context, Helper};
+use structopt::StructOpt;
+use structopt::clap::Shell;
+use crate::commands::command::Command;
+use crate::commands::spec::{command_spec, ArgSpec, CommandSpec};
+use crate::commands::manager::ServerManager;
+
+// Name the completion scripts are registered for
+const BIN_NAME: &str = "GServerManager";
+
+// Arguments whose value is a server name
+const SERVER_ARGS: &[&str] = &["name", "depends-on", "drop-depends-on"];
+
+// Arguments whose value is a filesystem path
+const PATH_ARGS: &[&str] = &["path", "log-path", "env-file", "venv", "dir", "root", "upstream"];
+
+// Candidates for the word under the cursor
+#[derive(Debug, PartialEq)]
+pub enum Completion {
+    Words(Vec<String>),
+    Path,
+}
+
+// Completes the word starting with `prefix` that follows `words` on a prompt line:
+// subcommands, then flags, then the values of the flag before it
+pub fn complete(words: &[&str], prefix: &str, server_names: &[String]) -> Completion {
+    let mut command = command_spec(&Command::clap());
+    let mut pending: Option<ArgSpec> = None;
+    for word in words {
+        if pending.take().is_some() {
+            continue;
+        }
+        if word.starts_with('-') {
+            pending = command.args.iter()
+                .filter(|arg| arg.takes_value)
+                .find(|arg| match word.strip_prefix("--") {
+                    Some(long) => arg.long.as_deref() == Some(long),
+                    None => word.len() == 2 && arg.short == word.chars().nth(1),
+                })
+                .cloned();
+        } else if let Some(index) = command.subcommands.iter().position(|subcommand| subcommand.name == *word) {
+            command = command.subcommands.swap_remove(index);
+        }
+    }
+
+    let candidates = match pending {
+        Some(arg) if PATH_ARGS.contains(&arg.name.as_str()) => return Completion::Path,
+        Some(arg) if SERVER_ARGS.contains(&arg.name.as_str()) => server_names.to_vec(),
+        Some(arg) => arg.possible_values,
+        None if prefix.starts_with('-') => command.args.iter()
+            .filter_map(|arg| arg.long.as_ref())
+            .map(|long| format!("--{}", long))
+            .collect(),
+        None if !command.subcommands.is_empty() => command.subcommands.iter()
+            .map(|subcommand| subcommand.name.clone())
+            .collect(),
+        None => command.positionals.iter()
+            .flat_map(|positional| positional.possible_values.clone())
+            .collect(),
+    };
+
+    let mut candidates = candidates.into_iter()
+        .filter(|candidate| candidate.starts_with(prefix))
+        .collect::<Vec<_>>();
+    candidates.sort();
+    candidates.dedup();
+    Completion::Words(candidates)
+}
+
+// Line editor helper of the prompt, `server_names` is refreshed after every command
+pub struct ReplHelper {
+    pub server_names: Vec<String>,
+    filenames: FilenameCompleter,
+}
+
+impl ReplHelper {
+    pub fn new(server_names: Vec<String>) -> ReplHelper {
+        ReplHelper {
+            server_names,
+            filenames: FilenameCompleter::new(),
+        }
+    }
+}
+
+impl Completer for ReplHelper {
+    type Candidate = Pair;
+
+    fn complete(&self, line: &str, pos: usize, ctx: &Context<'_>) -> rustyline::Result<(usize, Vec<Pair>)> {
+        let start = line[..pos].rfind(char::is_whitespace).map(|index| index + 1).unwrap_or(0);
+        let words = line[..start].split_whitespace().collect::<Vec<_>>();
+
+        match complete(&words, &line[start..pos], &self.server_names) {
+            Completion::Path => self.filenames.complete(line, pos, ctx),
+            Completion::Words(candidates) => Ok((start, candidates.into_iter()
+                .map(|candidate| Pair {
+                    replacement: format!("{} ", candidate),
+                    display: candidate,
+                })
+                .collect())),
+        }
+    }
+}
+
+impl Hinter for ReplHelper {
+    type Hint = String;
+}
+
+impl Highlighter for ReplHelper {}
+
+impl Validator for ReplHelper {}
+
+impl Helper for ReplHelper {}
+
+// Bash function wrapping the generated one to complete server names after FLAGS
+const BASH_SERVERS: &str = r#"_GServerManager_servers() {
+    case "${COMP_WORDS[COMP_CWORD-1]}" in
+        FLAGS)
+            COMPREPLY=($(compgen -W "$(GServerManager completions --servers 2>/dev/null)" -- "${COMP_WORDS[COMP_CWORD]}"))
+            ;;
+        *)
+            _GServerManager "$@"
+            ;;
+    esac
+}
+
+complete -F _GServerManager_servers -o bashdefault -o default GServerManager"#;
+
+// Zsh action completing server names
+const ZSH_SERVERS: &str = r#"_GServerManager_servers() {
+    local servers; servers=(${(f)"$(GServerManager completions --servers 2>/dev/null)"})
+    compadd -a servers
+}
+
+"#;
+
+// Fish arguments completing server names
+const FISH_SERVERS: &str = r#" -r -f -a "(GServerManager completions --servers 2>/dev/null)""#;
+
+// Completion script for the one-shot mode. Server names are completed by calling
+// back into the binary with `completions --servers`, so they follow the saved servers.
+pub fn completion_script(shell: Shell) -> String {
+    let mut script = Vec::new();
+    ServerManager::clap().gen_completions_to(BIN_NAME, shell, &mut script);
+    let script = String::from_utf8_lossy(&script).into_owned();
+
+    let mut longs = Vec::new();
+    let mut shorts = Vec::new();
+    server_flags(&command_spec(&ServerManager::clap()), &mut longs, &mut shorts);
+
+    match shell {
+        Shell::Bash => {
+            let flags = longs.iter()
+                .map(|long| format!("--{}", long))
+                .chain(shorts.iter().map(|short| format!("-{}", short)))
+                .collect::<Vec<_>>()
+                .join("|");
+            let registration = format!("complete -F _{} -o bashdefault -o default {}", BIN_NAME, BIN_NAME);
+            script.replace(&registration, &BASH_SERVERS.replace("FLAGS", &flags))
+        },
+        Shell::Zsh => {
+            let mut script = script.lines()
+                .map(|line| {
+                    let takes_server = longs.iter().any(|long| line.contains(&format!("'--{}=[", long)))
+                        || shorts.iter().any(|short| line.contains(&format!("'-{}+[", short)));
+                    // The action goes after the help text in brackets: '--name=[help]: :action'
+                    match line.rfind("]'") {
+                        Some(end) if takes_server => format!("{}: :_{}_servers{}\n", &line[..end + 1], BIN_NAME, &line[end + 1..]),
+                        _ => format!("{}\n", line),
+                    }
+                })
+                .collect::<String>();
+            // The helper must be defined before the entry point is called on the last line
+            let entry = script.rfind(&format!("_{} \"$@\"", BIN_NAME)).unwrap_or(script.len());
+            script.insert_str(entry, ZSH_SERVERS);
+            script
+        },
+        Shell::Fish => script.lines()
+            .map(|line| {
+                // The arguments go right after the flag, before its `-d` description
+                let flag = longs.iter()
+                    .map(|long| format!(" -l {}", long))
+                    .find(|flag| line.ends_with(flag.as_str()) || line.contains(&format!("{} -d ", flag)));
+                match flag {
+                    Some(flag) => format!("{}\n", line.replacen(&flag, &format!("{}{}", flag, FISH_SERVERS), 1)),
+                    None => format!("{}\n", line),
+                }
+            })
+            .collect(),
+        _ => script,
+    }
+}
+
+// Long and short flags taking a server name, in every subcommand
+fn server_flags(command: &CommandSpec, longs: &mut Vec<String>, shorts: &mut Vec<char>) {
+    for arg in command.args.iter().filter(|arg| arg.takes_value && SERVER_ARGS.contains(&arg.name.as_str())) {
+        if let Some(long) = &arg.long {
+            if !longs.contains(long) {
+                longs.push(long.clone());
+            }
+        }
+        if let Some(short) = arg.short {
+            if !shorts.contains(&short) {
+                shorts.push(short);
+            }
+        }
+    }
+    for subcommand in &command.subcommands {
+        server_flags(subcommand, longs, shorts);
+    }
+}
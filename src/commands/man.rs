@@ -0,0 +1,65 @@
+use structopt::StructOpt;
+use crate::commands::manager::ServerManager;
+use crate::commands::spec::{command_spec, CommandSpec};
+
+// Man page of the one-shot mode in roff, printed by `man`
+pub fn man_page() -> String {
+    let app = command_spec(&ServerManager::clap());
+    let mut page = String::new();
+    page.push_str(&format!(".TH GSERVERMANAGER 1 \"\" \"GServerManager {}\" \"User Commands\"\n", env!("CARGO_PKG_VERSION")));
+    page.push_str(".SH NAME\nGServerManager \\- manage your WSGI servers\n");
+    page.push_str(".SH SYNOPSIS\n\\fBGServerManager\\fR [\\fB--output\\fR \\fIformat\\fR] [\\fIcommand\\fR]\n");
+    page.push_str(".SH DESCRIPTION\n");
+    page.push_str("Without a command an interactive prompt is opened, with Tab completion and history. \
+        A command given on the command line is run once.\n");
+    page.push_str(".SH OPTIONS\n");
+    options(&app, &mut page);
+    page.push_str(".SH COMMANDS\n");
+    for subcommand in &app.subcommands {
+        command(subcommand, "", &mut page);
+    }
+    page
+}
+
+// Section of a command, followed by its nested subcommands
+fn command(command: &CommandSpec, parent: &str, page: &mut String) {
+    let name = format!("{}{}", parent, command.name);
+
+    let positionals = command.positionals.iter()
+        .map(|positional| format!(" \\fI{}\\fR", positional.name))
+        .collect::<String>();
+    page.push_str(&format!(".SS \"{}\"\n\\fBGServerManager {}\\fR{}\n", name, name, positionals));
+    if let Some(about) = &command.about {
+        page.push_str(&format!(".PP\n{}\n", escape(about)));
+    }
+    options(command, page);
+
+    for subcommand in &command.subcommands {
+        self::command(subcommand, &format!("{} ", name), page);
+    }
+}
+
+// Flags and options of a command
+fn options(command: &CommandSpec, page: &mut String) {
+    for arg in &command.args {
+        let value = match (arg.takes_value, arg.possible_values.is_empty()) {
+            (false, _) => String::new(),
+            (true, true) => format!(" \\fI{}\\fR", arg.name),
+            (true, false) => format!(" \\fI{}\\fR", arg.possible_values.join("|")),
+        };
+        page.push_str(&format!(".TP\n{}{}\n", switches(arg.short, arg.long.as_deref()), value));
+        if let Some(help) = &arg.help {
+            page.push_str(&format!("{}\n", escape(help)));
+        }
+    }
+}
+
+fn switches(short: Option<char>, long: Option<&str>) -> String {
+    let short = short.map(|short| format!("\\fB-{}\\fR", short));
+    let long = long.map(|long| format!("\\fB--{}\\fR", long));
+    short.into_iter().chain(long).collect::<Vec<_>>().join(", ")
+}
+
+fn escape(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('-', "\\-")
+}
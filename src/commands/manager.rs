@@ -1,11 +1,25 @@
 use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
 use structopt::StructOpt;
 
-use crate::commands::command::Command;
+use crate::commands::command::{Command, Target, EnvCommand, BuildCommand, HooksCommand, ReleasesCommand, BlueGreenCommand, AutoUpdateCommand, SnapshotsCommand, SetCommand};
 use crate::server::server::Server;
-use crate::server::servers::Servers;
+use crate::server::bulk;
+use crate::server::servers::{Servers, ServerEdit};
+use crate::error::GsmError;
 use crate::utils::hardware;
+use crate::utils::dotenv;
+use crate::utils::venv;
+use crate::utils::build::BuildStep;
+use crate::utils::detect;
+use crate::utils::output::{self, OutputFormat};
+use crate::utils::state;
+use crate::commands::completion;
+use crate::commands::man;
 use crate::github::utils;
+use crate::github::webhook;
+use crate::github::poller;
 
 
 #[derive(Debug, StructOpt)]
@@ -14,16 +28,31 @@ pub struct ServerManager {
     #[structopt(skip)]
     pub servers: Option<Servers>,
 
+    /// Print results as text or json
+    #[structopt(long, global = true, default_value = "text", possible_values = &["text", "json"])]
+    pub output: OutputFormat,
+
+    /// Directory holding the saved servers, pid files, logs and history,
+    /// defaults to $GSM_HOME or ~/.local/state/GServerManager
+    #[structopt(long, global = true, parse(from_os_str))]
+    pub state_dir: Option<PathBuf>,
+
     #[structopt(subcommand)]
     pub cmd: Option<Command>,
 }
 
+impl Default for ServerManager {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl ServerManager {
     pub fn new() -> ServerManager {
         ServerManager {
-            servers: Some(Servers {
-                servers: Vec::new(),
-            }),
+            servers: Some(Servers::new(Vec::new())),
+            output: OutputFormat::Text,
+            state_dir: None,
             cmd: None,
         }
     }
@@ -31,43 +60,111 @@ impl ServerManager {
     pub fn execute(&mut self) {
         let _original_dir = env::current_dir().unwrap();
         match &self.cmd {
-            Some(Command::Add { name, framework, path, workers, bind, port, timeout, log_path }) => {
-                if !path.exists() || !path.is_dir() {
-                    println!("Invalid server path");
+            Some(Command::Add { name, framework, app, path, git, rev, install, workers, bind, port, timeout, log_path, env_file, venv, tag, depends_on }) => {
+                let fail = |message: String| self.output.outcome("add", Some(name), Err(message));
+                if name.to_lowercase().contains("redis") {
+                    fail(String::from("Name reserved for Redis, use `redis --path [path to redis config file]` instead."));
                     return;
                 }
 
-                if name.to_lowercase().contains("redis") {
-                    println!("Name reserved for Redis, use `redis --path [path to redis config file]` instead.");
-                    return;
+                if let Some(env_file) = env_file {
+                    if !env_file.is_file() {
+                        fail(String::from("Invalid env file path"));
+                        return;
+                    }
+                }
+
+                let path = match git {
+                    Some(url) => {
+                        if self.servers.as_ref().is_some_and(|servers| servers.name_exists(name)) {
+                            fail(String::from("Server name already exists"));
+                            return;
+                        }
+                        let path = path.clone().unwrap_or_else(|| state::workspace_dir().join(name));
+                        if path.exists() {
+                            fail(format!("Clone destination {} already exists", path.display()));
+                            return;
+                        }
+                        if let Err(e) = clone_server(url, rev.as_deref(), &path) {
+                            fail(e);
+                            return;
+                        }
+                        path
+                    },
+                    None => match path {
+                        Some(path) if path.is_dir() => path.clone(),
+                        _ => {
+                            fail(String::from("Invalid server path"));
+                            return;
+                        }
+                    },
+                };
+                // Cloned directories are removed again if the server can't be added
+                let discard_clone = || {
+                    if git.is_some() {
+                        let _ = fs::remove_dir_all(&path);
+                    }
+                };
+
+                let venv = match venv.clone().or_else(|| venv::detect_venv(&path)) {
+                    Some(venv) => match venv::validate_venv(&venv) {
+                        Ok(venv) => Some(venv),
+                        Err(e) => {
+                            fail(e);
+                            discard_clone();
+                            return;
+                        }
+                    },
+                    None => None,
+                };
+
+                let framework = match framework {
+                    Some(framework) => framework.clone(),
+                    None => {
+                        let detected = detect::detect_framework(&path).unwrap_or("flask");
+                        eprintln!("Using framework {}", detected);
+                        detected.to_string()
+                    }
+                };
+
+                match app {
+                    Some(_) => (),
+                    None => match detect::detect_app(&path, &framework) {
+                        Some(detected) => eprintln!("Using entry point {}", detected),
+                        None => eprintln!("No entry point found, set one with `edit --name {} --app module:callable`", name),
+                    },
                 }
 
-                let log_path = log_path.as_ref().unwrap_or_else(|| path);
+                let log_path = log_path.clone().unwrap_or_else(state::logs_dir);
 
                 let server = Server {
-                    name: name.clone(),
-                    path: path.clone(),
                     bind: bind.clone(),
-                    port: *port,
                     workers: *workers,
                     timeout: *timeout,
-                    log_path: log_path.clone(),
-                    github: utils::is_git_repository(path),
-                    running: false,
-                    framework: framework.clone(),
+                    log_path,
+                    github: utils::is_git_repository(&path),
+                    framework,
                     original_dir: _original_dir.to_path_buf(),
-                    on_command: String::from("")
+                    env_file: env_file.clone(),
+                    venv,
+                    app: app.clone(),
+                    tags: tag.clone(),
+                    depends_on: depends_on.clone(),
+                    ..Server::new(name, path.clone(), *port)
                 };
                 if let Some(servers) = &mut self.servers {
-                    if let Err(e) = servers.add_server(server) {
-                        eprintln!("[ERROR] {}", e);
+                    let result = servers.add_server(server);
+                    if result.is_err() {
+                        discard_clone();
                     }
+                    let result = result.and_then(|_| if *install { servers.install(name) } else { Ok(()) });
+                    self.output.outcome("add", Some(name), result);
                 }
             },
 
             Some(Command::Redis { path, bind, port, log_path }) => {
                 if !path.exists() || !path.is_dir() {
-                    println!("Invalid redis config path");
+                    self.output.outcome("redis", None, Err("Invalid redis config path"));
                     return;
                 }
 
@@ -82,124 +179,450 @@ impl ServerManager {
                     }
                 }
 
-                let log_path = log_path.as_ref().unwrap_or_else(|| path);
+                let log_path = log_path.clone().unwrap_or_else(state::logs_dir);
 
                 let server = Server {
-                    name: name.clone(),
-                    path: path.clone(),
                     bind: bind.clone(),
-                    port: *port,
                     workers: 1,
-                    timeout: 30,
-                    log_path: log_path.clone(),
+                    log_path,
                     github: utils::is_git_repository(path),
-                    running: false,
                     framework: String::from("redis"),
                     original_dir: _original_dir.to_path_buf(),
-                    on_command: String::from("")
+                    ..Server::new(&name, path.clone(), *port)
                 };
                 if let Some(servers) = &mut self.servers {
-                    if let Err(e) = servers.add_server(server) {
-                        eprintln!("[ERROR] {}", e);
+                    self.output.outcome("redis", Some(&name), servers.add_server(server));
+                }
+            },
+
+            Some(Command::Edit { name, workers, bind, port, timeout, log_path, env_file, no_env_file, venv, no_venv, app, no_app, tag, untag, depends_on, drop_depends_on, restart }) => {
+                if let Some(log_path) = log_path {
+                    if !log_path.exists() || !log_path.is_dir() {
+                        self.output.outcome("edit", Some(name), Err("Invalid log path"));
+                        return;
+                    }
+                }
+
+                if let Some(env_file) = env_file {
+                    if !env_file.is_file() {
+                        self.output.outcome("edit", Some(name), Err("Invalid env file path"));
+                        return;
+                    }
+                }
+
+                let venv = match venv.as_ref().map(|venv| venv::validate_venv(venv)).transpose() {
+                    Ok(venv) => venv,
+                    Err(e) => {
+                        self.output.outcome("edit", Some(name), Err(e));
+                        return;
                     }
+                };
+
+                let edit = ServerEdit {
+                    port: *port,
+                    workers: *workers,
+                    timeout: *timeout,
+                    bind: bind.clone(),
+                    log_path: log_path.clone(),
+                    env_file: if *no_env_file { Some(None) } else { env_file.clone().map(Some) },
+                    venv: if *no_venv { Some(None) } else { venv.map(Some) },
+                    app: if *no_app { Some(None) } else { app.clone().map(Some) },
+                    tags: tag.clone(),
+                    untag: untag.clone(),
+                    depends_on: depends_on.clone(),
+                    drop_depends_on: drop_depends_on.clone(),
+                    restart: *restart,
+                };
+                if let Some(servers) = &mut self.servers {
+                    self.output.outcome("edit", Some(name), servers.edit_server(name, edit));
                 }
             },
 
-            Some(Command::Remove { name }) => {
+            Some(Command::Env(EnvCommand::Set { name, vars })) => {
+                let vars = match vars.iter().map(|v| dotenv::parse_assignment(v)).collect::<Result<Vec<_>, _>>() {
+                    Ok(vars) => vars,
+                    Err(e) => {
+                        self.output.outcome("env set", Some(name), Err(e));
+                        return;
+                    }
+                };
+                if let Some(servers) = &mut self.servers {
+                    self.output.outcome("env set", Some(name), servers.set_env(name, vars));
+                }
+            },
+
+            Some(Command::Env(EnvCommand::Unset { name, keys })) => {
                 if let Some(servers) = &mut self.servers {
-                    if let Err(e) = servers.remove_server(name) {
-                        eprintln!("[ERROR] {}", e);
+                    self.output.outcome("env unset", Some(name), servers.unset_env(name, keys));
+                }
+            },
+
+            Some(Command::Env(EnvCommand::List { name, show })) => {
+                if let Some(servers) = &self.servers {
+                    match servers.list_env(name, *show) {
+                        Ok(report) => self.output.print(&report),
+                        Err(e) => self.output.error(e),
+                    }
+                }
+            },
+
+            Some(Command::Build(BuildCommand::Add { name, command, dir, timeout, pattern, env })) => {
+                let env = match env.iter().map(|v| dotenv::parse_assignment(v)).collect::<Result<_, _>>() {
+                    Ok(env) => env,
+                    Err(e) => {
+                        self.output.outcome("build add", Some(name), Err(e));
+                        return;
                     }
+                };
+                let step = BuildStep {
+                    command: command.clone(),
+                    dir: dir.clone(),
+                    env,
+                    timeout: *timeout,
+                    patterns: pattern.clone(),
+                };
+                if let Some(servers) = &mut self.servers {
+                    self.output.outcome("build add", Some(name), servers.add_build_step(name, step));
                 }
             },
 
-            Some(Command::Start { name }) => {
+            Some(Command::Build(BuildCommand::Remove { name, index })) => {
                 if let Some(servers) = &mut self.servers {
-                    if let Err(e) = servers.start_server(name) {
-                        eprintln!("[ERROR] {}", e);
+                    self.output.outcome("build remove", Some(name), servers.remove_build_step(name, *index));
+                }
+            },
+
+            Some(Command::Build(BuildCommand::List { name })) => {
+                if let Some(servers) = &self.servers {
+                    match servers.list_build_steps(name) {
+                        Ok(report) => self.output.print(&report),
+                        Err(e) => self.output.error(e),
                     }
                 }
             },
 
-            Some(Command::Stop { name }) => {
+            Some(Command::Build(BuildCommand::Run { name })) => {
+                if let Some(servers) = &mut self.servers {
+                    self.output.outcome("build run", Some(name), servers.build(name));
+                }
+            },
+
+            Some(Command::Hooks(HooksCommand::Set { name, hook, command, timeout })) => {
+                if let Some(servers) = &mut self.servers {
+                    self.output.outcome("hooks set", Some(name), servers.set_hook(name, *hook, command.clone(), *timeout));
+                }
+            },
+
+            Some(Command::Hooks(HooksCommand::Unset { name, hook })) => {
                 if let Some(servers) = &mut self.servers {
-                    if let Err(e) = servers.stop_server(name) {
-                        eprintln!("[ERROR] {}", e);
+                    self.output.outcome("hooks unset", Some(name), servers.unset_hook(name, *hook));
+                }
+            },
+
+            Some(Command::Hooks(HooksCommand::List { name })) => {
+                if let Some(servers) = &self.servers {
+                    match servers.list_hooks(name) {
+                        Ok(report) => self.output.print(&report),
+                        Err(e) => self.output.error(e),
                     }
                 }
             },
 
-            Some(Command::Restart { name }) => {
+            Some(Command::Manage { name, args }) => {
+                if let Some(servers) = &self.servers {
+                    self.output.outcome("manage", Some(name), servers.manage(name, args));
+                }
+            },
+
+            Some(Command::Remove { name }) => {
+                if let Some(servers) = &mut self.servers {
+                    self.output.outcome("remove", Some(name), servers.remove_server(name));
+                }
+            },
+
+            Some(Command::Start { target }) => {
                 if let Some(servers) = &mut self.servers {
-                    if let Err(e) = servers.restart_server(name) {
-                        eprintln!("[ERROR] {}", e);
+                    let result = servers.select(target.name.as_deref(), &target.tag, target.all)
+                        .and_then(|names| servers.start_servers(&names, target.parallel));
+                    match result {
+                        Ok(results) => print_summary(self.output, "started", &results),
+                        Err(e) => self.output.error(e),
                     }
                 }
             },
 
-            Some(Command::Monitor { name }) => {
+            Some(Command::Stop { target }) => {
                 if let Some(servers) = &mut self.servers {
-                    if let Err(e) = servers.monitor(name) {
-                        eprintln!("[ERROR] {}", e);
+                    let result = servers.select(target.name.as_deref(), &target.tag, target.all)
+                        .and_then(|names| servers.stop_servers(&names, target.parallel));
+                    match result {
+                        Ok(results) => print_summary(self.output, "stopped", &results),
+                        Err(e) => self.output.error(e),
                     }
                 }
             },
 
-            Some(Command::ClearLogs { name }) => {
+            Some(Command::Restart { target }) => {
                 if let Some(servers) = &mut self.servers {
-                    if let Err(e) = servers.clear_logs(name) {
-                        eprintln!("[ERROR] {}", e);
+                    run_on_target(servers, target, self.output, "restarted", |server| server.restart());
+                }
+            },
+
+            Some(Command::Reload { target }) => {
+                if let Some(servers) = &mut self.servers {
+                    run_on_target(servers, target, self.output, "reloaded", |server| server.reload());
+                }
+            },
+
+            Some(Command::Monitor { name }) => {
+                if let Some(servers) = &self.servers {
+                    match servers.monitor(name) {
+                        Ok(report) => self.output.print(&report),
+                        Err(e) => self.output.error(e),
                     }
                 }
             },
 
-            Some(Command::List {}) => {
+            Some(Command::ClearLogs { name }) => {
+                if let Some(servers) = &mut self.servers {
+                    self.output.outcome("clear_logs", Some(name), servers.clear_logs(name));
+                }
+            },
+
+            Some(Command::List) => {
+                if let Some(servers) = &self.servers {
+                    self.output.print(&servers.list_all());
+                }
+            },
+
+            Some(Command::Flush) => {
                 if let Some(servers) = &mut self.servers {
-                    servers.list_all();
+                    self.output.outcome("flush", None, servers.flush());
                 }
             },
 
-            Some(Command::Flush {}) => {
+            Some(Command::Snapshots(SnapshotsCommand::List)) => {
+                if let Some(servers) = &self.servers {
+                    match servers.list_snapshots() {
+                        Ok(snapshots) => self.output.print(&snapshots),
+                        Err(e) => self.output.error(e),
+                    }
+                }
+            },
+
+            Some(Command::Snapshots(SnapshotsCommand::Restore { id, dry_run, yes })) => {
                 if let Some(servers) = &mut self.servers {
-                    servers.flush();
+                    let result = servers.snapshot_diff(*id).and_then(|diff| {
+                        if diff.is_empty() {
+                            eprintln!("[INFO] Snapshot {} matches the current servers", id);
+                            return Ok(());
+                        }
+                        for line in &diff {
+                            eprintln!("{}", line);
+                        }
+                        if *dry_run {
+                            Ok(())
+                        } else if *yes || confirm(&format!("Restore snapshot {}?", id)) {
+                            servers.restore_snapshot(*id)
+                        } else {
+                            Err(GsmError::InvalidState(format!("Restoring snapshot {} was not confirmed, use --yes to restore it without asking", id)))
+                        }
+                    });
+                    self.output.outcome("snapshots restore", None, result);
                 }
             },
 
-            Some(Command::Hardware {}) => {
-                hardware::monitor_system_info();
+            Some(Command::Hardware) => {
+                self.output.print(&hardware::monitor_system_info());
             },
 
             Some(Command::GitInit { name }) => {
                 if let Some(servers) = &mut self.servers {
-                    servers.git_init(name);
+                    self.output.outcome("git_init", Some(name), servers.git_init(name));
                 }
             },
 
             Some(Command::AddOrigin { name, remote_url }) => {
                 if let Some(servers) = &mut self.servers {
-                    servers.add_origin(name, remote_url);
+                    self.output.outcome("add_origin", Some(name), servers.add_origin(name, remote_url));
+                }
+            },
+
+            Some(Command::GitStatus { name }) => {
+                if let Some(servers) = &self.servers {
+                    match servers.git_status(name) {
+                        Ok(report) => self.output.print(&report),
+                        Err(e) => self.output.error(e),
+                    }
+                }
+            },
+
+            Some(Command::Update { target }) => {
+                if let Some(servers) = &mut self.servers {
+                    run_on_target(servers, target, self.output, "updated", |server| server.update());
+                }
+            },
+
+            Some(Command::Deploy { name, rev }) => {
+                if let Some(servers) = &mut self.servers {
+                    self.output.outcome("deploy", Some(name), servers.deploy(name, rev));
+                }
+            },
+
+            Some(Command::Rollback { name }) => {
+                if let Some(servers) = &mut self.servers {
+                    self.output.outcome("rollback", Some(name), servers.rollback(name));
+                }
+            },
+
+            Some(Command::Releases(ReleasesCommand::Enable { name, root, keep })) => {
+                if let Some(servers) = &mut self.servers {
+                    self.output.outcome("releases enable", Some(name), servers.enable_releases(name, root, *keep));
+                }
+            },
+
+            Some(Command::Releases(ReleasesCommand::List { name })) => {
+                if let Some(servers) = &self.servers {
+                    match servers.list_releases(name) {
+                        Ok(report) => self.output.print(&report),
+                        Err(e) => self.output.error(e),
+                    }
+                }
+            },
+
+            Some(Command::BlueGreen(BlueGreenCommand::Enable { name, green_port, upstream, proxy_reload })) => {
+                if let Some(servers) = &mut self.servers {
+                    self.output.outcome("blue_green enable", Some(name), servers.enable_blue_green(name, *green_port, upstream.clone(), proxy_reload.clone()));
                 }
             },
 
-            Some(Command::Update { name }) => {
+            Some(Command::BlueGreen(BlueGreenCommand::Disable { name })) => {
                 if let Some(servers) = &mut self.servers {
-                    servers.update(name);
+                    self.output.outcome("blue_green disable", Some(name), servers.disable_blue_green(name));
+                }
+            },
+
+            Some(Command::BlueGreen(BlueGreenCommand::Switch { name })) => {
+                if let Some(servers) = &mut self.servers {
+                    self.output.outcome("blue_green switch", Some(name), servers.switch_colors(name));
+                }
+            },
+
+            Some(Command::Webhook { bind, port, secret }) => {
+                if let Some(servers) = &mut self.servers {
+                    if let Err(e) = webhook::serve(servers, bind, *port, secret.as_deref()) {
+                        self.output.error(e);
+                    }
+                }
+            },
+
+            Some(Command::AutoUpdate(AutoUpdateCommand::Enable { name, interval, window })) => {
+                if let Some(servers) = &mut self.servers {
+                    self.output.outcome("auto_update enable", Some(name), servers.enable_auto_update(name, *interval, *window));
+                }
+            },
+
+            Some(Command::AutoUpdate(AutoUpdateCommand::Disable { name })) => {
+                if let Some(servers) = &mut self.servers {
+                    self.output.outcome("auto_update disable", Some(name), servers.disable_auto_update(name));
+                }
+            },
+
+            Some(Command::AutoUpdate(AutoUpdateCommand::Check)) => {
+                if let Some(servers) = &mut self.servers {
+                    servers.check_updates(poller::now_secs());
+                }
+            },
+
+            Some(Command::AutoUpdate(AutoUpdateCommand::Run)) => {
+                if let Some(servers) = &mut self.servers {
+                    poller::run(servers);
                 }
             },
 
             Some(Command::Visualize { name, show }) => {
                 if name.to_lowercase().contains("redis") {
-                    println!("Visualization for Redis servers not implemented.");
+                    self.output.outcome("visualize", Some(name), Err("Visualization for Redis servers not implemented."));
                     return;
                 }
                 if let Some(servers) = &mut self.servers {
-                    servers.visualize(name, show);
+                    self.output.outcome("visualize", Some(name), servers.visualize(name, show));
                 }
             },
 
+            Some(Command::Set(SetCommand::Output { format })) => {
+                self.output = *format;
+                println!("Output format set to {}", format);
+            },
+
+            Some(Command::Completions { shell, servers: list_servers }) => {
+                if *list_servers {
+                    if let Some(servers) = &self.servers {
+                        for server in &servers.servers {
+                            println!("{}", server.name);
+                        }
+                    }
+                } else if let Some(shell) = shell {
+                    print!("{}", completion::completion_script(*shell));
+                }
+            },
+
+            Some(Command::Man) => {
+                print!("{}", man::man_page());
+            },
+
             None => {
                 println!("No command provided. Use --help to see available commands.");
             }
         }
     }
 }
+
+// Runs `operation` concurrently on every server selected by `target` and prints a summary
+fn run_on_target<F>(servers: &mut Servers, target: &Target, output: OutputFormat, action: &str, operation: F)
+where
+    F: Fn(&mut Server) -> Result<(), GsmError> + Sync,
+{
+    let result = servers.select(target.name.as_deref(), &target.tag, target.all)
+        .and_then(|names| servers.run_bulk(&names, target.parallel, operation));
+    match result {
+        Ok(results) => print_summary(output, action, &results),
+        Err(e) => output.error(e),
+    }
+}
+
+// Prints the summary of a bulk command, which failed if any of its servers did
+fn print_summary(output: OutputFormat, action: &str, results: &[bulk::OperationResult]) {
+    if results.iter().any(|result| result.status == bulk::Status::Failed) {
+        output::set_failed();
+    }
+    output.print(&bulk::Summary { action, results });
+}
+
+// Clones a repository for a new server and checks out `rev` if given
+fn clone_server(url: &str, rev: Option<&str>, path: &Path) -> Result<(), String> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).map_err(|e| format!("Failed to create {}: {}", parent.display(), e))?;
+    }
+    eprintln!("Cloning {} into {}...", url, path.display());
+    utils::git_clone(url, path, false)?;
+
+    if let Some(rev) = rev {
+        if let Err(e) = utils::git_checkout(rev, path) {
+            let _ = fs::remove_dir_all(path);
+            return Err(format!("Failed to check out {}: {}", rev, e));
+        }
+    }
+    Ok(())
+}
+
+// Asks `question` on stderr and reads the answer, anything but `y`/`yes` (or no answer) declines
+fn confirm(question: &str) -> bool {
+    eprint!("{} [y/N] ", question);
+    let mut answer = String::new();
+    if !matches!(std::io::stdin().read_line(&mut answer), Ok(read) if read > 0) {
+        eprintln!();
+        return false;
+    }
+    matches!(answer.trim().to_lowercase().as_str(), "y" | "yes")
+}
@@ -1,2 +1,5 @@
 pub mod manager;
-pub mod command;
\ No newline at end of file
+pub mod command;
+pub mod completion;
+pub mod man;
+pub mod spec;
@@ -0,0 +1,134 @@
+use std::iter;
+use structopt::clap::{App, AppSettings, ErrorKind};
+
+// Plain description of a command built from its help, so completion and the man page
+// only go through clap's public API
+#[derive(Clone, Debug)]
+pub struct CommandSpec {
+    pub name: String,
+    pub about: Option<String>,
+    pub args: Vec<ArgSpec>, // Flags then options, without the ones clap adds
+    pub positionals: Vec<ArgSpec>,
+    pub subcommands: Vec<CommandSpec>,
+}
+
+#[derive(Clone, Debug)]
+pub struct ArgSpec {
+    pub name: String,
+    pub short: Option<char>,
+    pub long: Option<String>,
+    pub help: Option<String>,
+    pub takes_value: bool,
+    pub possible_values: Vec<String>,
+}
+
+// Reads every command of `app` from the `-h` output clap returns as an error when parsing it.
+// Hidden arguments aren't in the help, so they are left out.
+pub fn command_spec(app: &App) -> CommandSpec {
+    // No terminal width, so every argument stays on one line, and arguments in the order they are declared
+    let mut app = app.clone()
+        .set_term_width(0)
+        .global_setting(AppSettings::DeriveDisplayOrder);
+    let name = app.get_name().to_string();
+    let mut spec = read_command(&mut app, &[], name, None);
+
+    // Global options show up in the help of every subcommand, they belong to the root
+    let globals = spec.args.iter().map(|arg| arg.name.clone()).collect::<Vec<_>>();
+    for subcommand in &mut spec.subcommands {
+        remove_args(subcommand, &globals);
+    }
+    spec
+}
+
+fn read_command(app: &mut App, path: &[String], name: String, about: Option<String>) -> CommandSpec {
+    let mut spec = CommandSpec {
+        name,
+        about,
+        args: Vec::new(),
+        positionals: Vec::new(),
+        subcommands: Vec::new(),
+    };
+
+    let mut section = "";
+    for line in help(app, path).lines() {
+        if !line.starts_with(' ') {
+            section = line.trim_end_matches(':');
+            continue;
+        }
+        let (entry, help) = match line.trim().split_once("  ") {
+            Some((entry, help)) => (entry, help.trim()),
+            None => (line.trim(), ""),
+        };
+        match section {
+            "FLAGS" | "OPTIONS" => {
+                let arg = read_arg(entry, help, section == "OPTIONS");
+                if !["help", "version"].contains(&arg.name.as_str()) {
+                    spec.args.push(arg);
+                }
+            },
+            "ARGS" => spec.positionals.push(read_arg(entry, help, true)),
+            "SUBCOMMANDS" if entry != "help" => {
+                let subcommand = path.iter().cloned().chain(iter::once(entry.to_string())).collect::<Vec<_>>();
+                let about = Some(help.to_string()).filter(|about| !about.is_empty());
+                spec.subcommands.push(read_command(app, &subcommand, entry.to_string(), about));
+            },
+            _ => {},
+        }
+    }
+    spec
+}
+
+// Help of the subcommand at `path`
+fn help(app: &mut App, path: &[String]) -> String {
+    let args = iter::once(app.get_name().to_string())
+        .chain(path.iter().cloned())
+        .chain(iter::once(String::from("-h")));
+    match app.get_matches_from_safe_borrow(args) {
+        Err(error) if error.kind == ErrorKind::HelpDisplayed => error.message,
+        _ => String::new(),
+    }
+}
+
+// One help entry: `-n, --name <name>`, `--dry-run` or `<id>...`, with the possible values
+// clap appends to the help text
+fn read_arg(entry: &str, help: &str, takes_value: bool) -> ArgSpec {
+    let mut arg = ArgSpec {
+        name: String::new(),
+        short: None,
+        long: None,
+        help: None,
+        takes_value,
+        possible_values: Vec::new(),
+    };
+    for word in entry.split([',', ' ']).filter(|word| !word.is_empty()) {
+        if let Some(long) = word.strip_prefix("--") {
+            arg.long = Some(long.to_string());
+        } else if let Some(short) = word.strip_prefix('-') {
+            arg.short = short.chars().next();
+        } else if arg.name.is_empty() {
+            arg.name = word.trim_start_matches('<').trim_end_matches("...").trim_end_matches('>').to_string();
+        }
+    }
+    // Flags have no value name
+    if arg.name.is_empty() {
+        arg.name = arg.long.clone().unwrap_or_default();
+    }
+
+    let (help, possible_values) = match help.split_once("[possible values: ") {
+        Some((help, values)) => (help.trim(), values.trim_end_matches(']')),
+        None => (help, ""),
+    };
+    arg.help = Some(help.to_string()).filter(|help| !help.is_empty());
+    arg.possible_values = possible_values.split(", ")
+        .filter(|value| !value.is_empty())
+        .map(|value| value.to_string())
+        .collect();
+    arg
+}
+
+fn remove_args(command: &mut CommandSpec, names: &[String]) {
+    command.args.retain(|arg| !names.contains(&arg.name));
+    for subcommand in &mut command.subcommands {
+        remove_args(subcommand, names);
+    }
+}
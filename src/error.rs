@@ -0,0 +1,65 @@
+use std::fmt;
+use std::io;
+use std::error::Error;
+use std::path::PathBuf;
+use std::process::ExitStatus;
+
+// Errors returned by the operations on `Servers` and `Server`, so callers can
+// tell a missing server from a port conflict or a failed build
+#[derive(Debug)]
+pub enum GsmError {
+    NotFound(String), // No server with this name
+    NameTaken(String), // Name of a new server already used by another one
+    PathInUse(PathBuf), // Directory already served by another server
+    PortInUse(u32), // Port already reserved by another server
+    InvalidServerDir(PathBuf), // Directory without a servable entry point
+    InvalidArgument(String), // Settings or selections rejected before doing anything
+    InvalidState(String), // Operation not possible in the server's current state
+    SpawnFailed { action: String, stderr: String }, // A command ran but exited with an error
+    Exited { action: String, status: ExitStatus }, // A command attached to the terminal exited with an error
+    Git(String), // A git command failed
+    Build(String), // Dependency sync, build or deploy step failed
+    Hook(String), // A `pre_*` lifecycle hook failed
+    Dependency(String), // Unknown dependency or dependency cycle between servers
+    HealthCheck(String), // Server didn't come up in time
+    Backup(String), // Servers file could not be written or read
+    Io(io::Error),
+}
+
+impl fmt::Display for GsmError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            GsmError::NotFound(name) => write!(f, "Server [{}] not found", name),
+            GsmError::NameTaken(name) => write!(f, "Server name [{}] already exists", name),
+            GsmError::PathInUse(path) => write!(f, "Server path {} already exists", path.display()),
+            GsmError::PortInUse(port) => write!(f, "Server port {} already exists", port),
+            GsmError::InvalidServerDir(path) => write!(f, "Not a valid server directory: {}", path.display()),
+            GsmError::SpawnFailed { action, stderr } => write!(f, "Failed to {}: {}", action, stderr.trim()),
+            GsmError::Exited { action, status } => write!(f, "Failed to {}: {}", action, status),
+            GsmError::InvalidArgument(message)
+            | GsmError::InvalidState(message)
+            | GsmError::Git(message)
+            | GsmError::Build(message)
+            | GsmError::Hook(message)
+            | GsmError::Dependency(message)
+            | GsmError::HealthCheck(message)
+            | GsmError::Backup(message) => write!(f, "{}", message),
+            GsmError::Io(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl Error for GsmError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            GsmError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for GsmError {
+    fn from(e: io::Error) -> Self {
+        GsmError::Io(e)
+    }
+}
@@ -1 +1,3 @@
-pub mod utils;
\ No newline at end of file
+pub mod utils;
+pub mod webhook;
+pub mod poller;
\ No newline at end of file
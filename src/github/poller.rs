@@ -0,0 +1,94 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use serde::{Serialize, Deserialize};
+use crate::server::servers::Servers;
+
+// Polling settings of a server that updates itself when new commits are pushed
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct AutoUpdate {
+    pub interval: u64, // Seconds between two polls
+    #[serde(default)]
+    pub window: Option<MaintenanceWindow>, // Updates are only applied inside this window if set
+}
+
+// Daily time range in UTC, given as `HH:MM-HH:MM`, that may wrap past midnight
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct MaintenanceWindow {
+    pub start: u32, // Minutes since midnight
+    pub end: u32,
+}
+
+impl MaintenanceWindow {
+    pub fn contains(&self, minute: u32) -> bool {
+        if self.start <= self.end {
+            self.start <= minute && minute < self.end
+        } else {
+            minute >= self.start || minute < self.end
+        }
+    }
+}
+
+impl FromStr for MaintenanceWindow {
+    type Err = String;
+
+    fn from_str(window: &str) -> Result<Self, Self::Err> {
+        let (start, end) = window.split_once('-')
+            .ok_or(format!("Invalid maintenance window '{}', expected HH:MM-HH:MM", window))?;
+        Ok(MaintenanceWindow {
+            start: parse_minutes(start)?,
+            end: parse_minutes(end)?,
+        })
+    }
+}
+
+impl fmt::Display for MaintenanceWindow {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:02}:{:02}-{:02}:{:02} UTC", self.start / 60, self.start % 60, self.end / 60, self.end % 60)
+    }
+}
+
+fn parse_minutes(time: &str) -> Result<u32, String> {
+    let invalid = || format!("Invalid time '{}', expected HH:MM", time);
+    let (hours, minutes) = time.trim().split_once(':').ok_or_else(invalid)?;
+    let hours: u32 = hours.parse().map_err(|_| invalid())?;
+    let minutes: u32 = minutes.parse().map_err(|_| invalid())?;
+    if hours > 23 || minutes > 59 {
+        return Err(invalid());
+    }
+    Ok(hours * 60 + minutes)
+}
+
+pub fn now_secs() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0)
+}
+
+// Current UTC time as minutes since midnight
+pub fn utc_minute(secs: u64) -> u32 {
+    ((secs / 60) % (24 * 60)) as u32
+}
+
+// Polls the servers with auto update enabled forever, each at its own interval
+pub fn run(servers: &mut Servers) {
+    eprintln!("Polling for new commits, press CTRL-C to stop");
+    let mut last_polls: HashMap<String, u64> = HashMap::new();
+
+    loop {
+        let now = now_secs();
+        let due = servers.servers.iter()
+            .filter_map(|server| server.auto_update.as_ref().map(|auto_update| (server.name.clone(), auto_update.interval)))
+            .filter(|(name, interval)| last_polls.get(name).is_none_or(|last| now >= last + interval))
+            .map(|(name, _)| name)
+            .collect::<Vec<_>>();
+
+        for name in due {
+            last_polls.insert(name.clone(), now);
+            if let Err(e) = servers.poll_update(&name, now) {
+                eprintln!("[ERROR] [{}] {}", name, e);
+            }
+        }
+        thread::sleep(Duration::from_secs(1));
+    }
+}
@@ -1,15 +1,24 @@
 use std::path::Path;
 use std::process::Command;
+use serde::Serialize;
 
 pub fn is_git_repository(path: &Path) -> bool {
     let output = Command::new("git")
         .arg("rev-parse")
         .arg("--is-inside-work-tree")
         .current_dir(path)
-        .output()
-        .expect("Failed to execute Git command.");
+        .output();
 
-    output.status.success()
+    // A missing git binary or directory means there's no repository to use either
+    output.is_ok_and(|output| output.status.success())
+}
+
+// Whether `path` is the top of its work tree, not a directory nested inside a repository
+pub fn is_repository_root(path: &Path) -> bool {
+    match (git_output(&["rev-parse", "--show-toplevel"], path), path.canonicalize()) {
+        (Ok(toplevel), Ok(path)) => Path::new(&toplevel).canonicalize().is_ok_and(|toplevel| toplevel == path),
+        _ => false,
+    }
 }
 
 pub fn initialize_git_repository(path: &Path) -> Result<(), String> {
@@ -17,59 +26,311 @@ pub fn initialize_git_repository(path: &Path) -> Result<(), String> {
         .arg("init")
         .current_dir(path)
         .output()
-        .expect("Failed to execute Git command.");
+        .map_err(|e| format!("Failed to execute Git command: {}", e))?;
 
     if output.status.success() {
         Ok(())
     } else {
         let error_message = String::from_utf8_lossy(&output.stderr).trim().to_owned();
-        Err(error_message.into())
+        Err(error_message)
     }
 }
 
 pub fn add_remote_origin(path: &Path, remote_url: &str) -> Result<(), String> {
-    println!("{}", path.display());
     let output = Command::new("git")
-        .args(&["remote", "add", "origin", remote_url])
+        .args(["remote", "add", "origin", remote_url])
         .current_dir(path)
         .output()
-        .expect("Failed to execute Git command.");
+        .map_err(|e| format!("Failed to execute Git command: {}", e))?;
 
     if output.status.success() {
         Ok(())
     } else {
         let error_message = String::from_utf8_lossy(&output.stderr).trim().to_owned();
-        Err(error_message.into())
+        Err(error_message)
     }
 }
 
 pub fn git_pull(path: &Path) -> Result<(), String> {
     let output = Command::new("git")
-        .args(&["pull"])
+        .args(["pull"])
         .current_dir(path)
         .output()
-        .expect("Failed to execute Git command.");
+        .map_err(|e| format!("Failed to execute Git command: {}", e))?;
 
     if output.status.success() {
         Ok(())
     } else {
         let error_message = String::from_utf8_lossy(&output.stderr).trim().to_owned();
-        Err(error_message.into())
+        Err(error_message)
     }
 }
 
 pub fn git_diff_name_only(from: &str, to: &str, path: &Path) -> Result<String, String> {
     let output = Command::new("git")
-        .args(&["diff", "--name-only", from, to])
+        .args(["diff", "--name-only", from, to])
         .current_dir(path)
         .output()
         .map_err(|e| format!("Failed to execute Git command: {}", e))?;
 
     if output.status.success() {
         let diff_output = String::from_utf8_lossy(&output.stdout).trim().to_owned();
-        Ok(diff_output.into())
+        Ok(diff_output)
+    } else {
+        let error_message = String::from_utf8_lossy(&output.stderr).trim().to_owned();
+        Err(error_message)
+    }
+}
+
+pub fn git_fetch(path: &Path) -> Result<(), String> {
+    let output = Command::new("git")
+        .args(["fetch", "--tags", "--prune", "origin"])
+        .current_dir(path)
+        .output()
+        .map_err(|e| format!("Failed to execute Git command: {}", e))?;
+
+    if output.status.success() {
+        Ok(())
+    } else {
+        let error_message = String::from_utf8_lossy(&output.stderr).trim().to_owned();
+        Err(error_message)
+    }
+}
+
+// Resolves a branch, tag or commit to a full commit hash, `None` if it doesn't exist
+pub fn git_rev_parse(rev: &str, path: &Path) -> Result<Option<String>, String> {
+    let output = Command::new("git")
+        .args(["rev-parse", "--verify", "--quiet", &format!("{}^{{commit}}", rev)])
+        .current_dir(path)
+        .output()
+        .map_err(|e| format!("Failed to execute Git command: {}", e))?;
+
+    if output.status.success() {
+        Ok(Some(String::from_utf8_lossy(&output.stdout).trim().to_owned()))
+    } else {
+        Ok(None)
+    }
+}
+
+// Checks out a remote branch as a local branch reset to the remote, or any other
+// ref (tag, commit) as a detached HEAD. A local branch with commits that aren't on
+// the remote is never reset.
+pub fn git_checkout(rev: &str, path: &Path) -> Result<(), String> {
+    let remote_branch = format!("origin/{}", rev);
+    let args = if git_rev_parse(&remote_branch, path)?.is_some() {
+        let local_branch = format!("refs/heads/{}", rev);
+        if git_rev_parse(&local_branch, path)?.is_some() && !git_is_ancestor(&local_branch, &remote_branch, path)? {
+            return Err(format!("Local branch {} has commits that are not on {}, merge or drop them first.", rev, remote_branch));
+        }
+        vec!["checkout", "-B", rev, remote_branch.as_str()]
+    } else {
+        vec!["checkout", "--detach", rev]
+    };
+
+    let output = Command::new("git")
+        .args(&args)
+        .current_dir(path)
+        .output()
+        .map_err(|e| format!("Failed to execute Git command: {}", e))?;
+
+    if output.status.success() {
+        Ok(())
+    } else {
+        let error_message = String::from_utf8_lossy(&output.stderr).trim().to_owned();
+        Err(error_message)
+    }
+}
+
+
+// Fast-forwards the checked out branch to its remote branch, fails if they have diverged
+pub fn git_fast_forward(branch: &str, path: &Path) -> Result<(), String> {
+    let output = Command::new("git")
+        .args(["merge", "--ff-only", &format!("origin/{}", branch)])
+        .current_dir(path)
+        .output()
+        .map_err(|e| format!("Failed to execute Git command: {}", e))?;
+
+    if output.status.success() {
+        Ok(())
+    } else {
+        let error_message = String::from_utf8_lossy(&output.stderr).trim().to_owned();
+        Err(error_message)
+    }
+}
+
+
+pub fn git_clone(url: &str, destination: &Path, mirror: bool) -> Result<(), String> {
+    let mut command = Command::new("git");
+    command.args(["clone", "--quiet"]);
+    if mirror {
+        command.arg("--mirror");
+    }
+    let output = command
+        .arg(url)
+        .arg(destination)
+        .output()
+        .map_err(|e| format!("Failed to execute Git command: {}", e))?;
+
+    if output.status.success() {
+        Ok(())
     } else {
         let error_message = String::from_utf8_lossy(&output.stderr).trim().to_owned();
-        Err(error_message.into())
+        Err(error_message)
     }
-}
\ No newline at end of file
+}
+
+// Fetches every ref of a mirror clone, dropping the ones deleted upstream
+pub fn git_remote_update(path: &Path) -> Result<(), String> {
+    let output = Command::new("git")
+        .args(["remote", "update", "--prune"])
+        .current_dir(path)
+        .output()
+        .map_err(|e| format!("Failed to execute Git command: {}", e))?;
+
+    if output.status.success() {
+        Ok(())
+    } else {
+        let error_message = String::from_utf8_lossy(&output.stderr).trim().to_owned();
+        Err(error_message)
+    }
+}
+
+pub fn git_remote_url(path: &Path) -> Result<Option<String>, String> {
+    let output = Command::new("git")
+        .args(["remote", "get-url", "origin"])
+        .current_dir(path)
+        .output()
+        .map_err(|e| format!("Failed to execute Git command: {}", e))?;
+
+    if output.status.success() {
+        Ok(Some(String::from_utf8_lossy(&output.stdout).trim().to_owned()))
+    } else {
+        Ok(None)
+    }
+}
+
+
+// Branch checked out in the repository, `None` on a detached HEAD
+pub fn git_current_branch(path: &Path) -> Result<Option<String>, String> {
+    let output = Command::new("git")
+        .args(["symbolic-ref", "--quiet", "--short", "HEAD"])
+        .current_dir(path)
+        .output()
+        .map_err(|e| format!("Failed to execute Git command: {}", e))?;
+
+    if output.status.success() {
+        Ok(Some(String::from_utf8_lossy(&output.stdout).trim().to_owned()))
+    } else {
+        Ok(None)
+    }
+}
+
+// Reduces the different forms of a remote URL (https, ssh, scp-like, with or
+// without credentials and `.git`) to `host/owner/repo` so they can be compared
+pub fn normalize_remote_url(url: &str) -> String {
+    let url = url.trim().trim_end_matches('/');
+    let url = url.strip_suffix(".git").unwrap_or(url);
+    let url = match url.split_once("://") {
+        Some((_, rest)) => rest.to_string(),
+        // scp-like syntax: git@host:owner/repo
+        None => url.replacen(':', "/", 1),
+    };
+    let url = match url.split_once('@') {
+        Some((_, rest)) => rest,
+        None => url.as_str(),
+    };
+    // Drop an explicit port from the host
+    let (host, path) = url.split_once('/').unwrap_or((url, ""));
+    let host = host.split(':').next().unwrap_or(host);
+    format!("{}/{}", host, path).to_lowercase()
+}
+
+
+// Whether `ancestor` is reachable from `descendant`, i.e. a pull would fast-forward
+pub fn git_is_ancestor(ancestor: &str, descendant: &str, path: &Path) -> Result<bool, String> {
+    let output = Command::new("git")
+        .args(["merge-base", "--is-ancestor", ancestor, descendant])
+        .current_dir(path)
+        .output()
+        .map_err(|e| format!("Failed to execute Git command: {}", e))?;
+
+    match output.status.code() {
+        Some(0) => Ok(true),
+        Some(1) => Ok(false),
+        _ => {
+            let error_message = String::from_utf8_lossy(&output.stderr).trim().to_owned();
+            Err(error_message)
+        }
+    }
+}
+
+
+// Snapshot of a repository as shown by `git_status`
+#[derive(Debug, Default, Serialize)]
+pub struct GitStatus {
+    pub branch: Option<String>, // `None` on a detached HEAD
+    pub head: String,
+    pub subject: String,
+    pub upstream: Option<String>,
+    pub ahead: u32,
+    pub behind: u32,
+    pub dirty: Vec<String>, // `git status --porcelain` lines
+    pub incoming: Vec<String>, // Files changed by the commits an update would bring in
+}
+
+fn git_output(args: &[&str], path: &Path) -> Result<String, String> {
+    let output = Command::new("git")
+        .args(args)
+        .current_dir(path)
+        .output()
+        .map_err(|e| format!("Failed to execute Git command: {}", e))?;
+
+    if output.status.success() {
+        Ok(String::from_utf8_lossy(&output.stdout).trim_end().to_owned())
+    } else {
+        let error_message = String::from_utf8_lossy(&output.stderr).trim().to_owned();
+        Err(error_message)
+    }
+}
+
+// Reads the state of a working tree against its upstream branch, without fetching
+pub fn git_status(path: &Path) -> Result<GitStatus, String> {
+    let mut status = GitStatus {
+        branch: git_current_branch(path)?,
+        ..Default::default()
+    };
+
+    let head = git_output(&["log", "-1", "--format=%H%n%s"], path)?;
+    let mut lines = head.lines();
+    status.head = lines.next().unwrap_or_default().to_string();
+    status.subject = lines.next().unwrap_or_default().to_string();
+
+    status.dirty = git_output(&["status", "--porcelain"], path)?
+        .lines()
+        .map(|line| line.to_string())
+        .collect();
+
+    if let Ok(upstream) = git_output(&["rev-parse", "--abbrev-ref", "@{upstream}"], path) {
+        let counts = git_output(&["rev-list", "--left-right", "--count", "HEAD...@{upstream}"], path)?;
+        let mut counts = counts.split_whitespace().map(|count| count.parse().unwrap_or(0));
+        status.ahead = counts.next().unwrap_or(0);
+        status.behind = counts.next().unwrap_or(0);
+        if status.behind > 0 {
+            // Three dots: only the upstream's side since the merge base, not the local commits
+            status.incoming = git_output(&["diff", "--name-only", "HEAD...@{upstream}"], path)?
+                .lines()
+                .map(|line| line.to_string())
+                .collect();
+        }
+        status.upstream = Some(upstream);
+    }
+    Ok(status)
+}
+
+
+// Number of commits reachable from `to` but not from `from`
+pub fn git_commit_count(from: &str, to: &str, path: &Path) -> Result<u32, String> {
+    git_output(&["rev-list", "--count", &format!("{}..{}", from, to)], path)?
+        .parse()
+        .map_err(|e| format!("Invalid commit count: {}", e))
+}
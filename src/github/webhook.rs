@@ -0,0 +1,258 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{IpAddr, TcpListener, TcpStream};
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+use hmac::{Hmac, Mac};
+use serde_json::Value;
+use sha2::Sha256;
+use crate::github::utils::normalize_remote_url;
+use crate::server::servers::Servers;
+use crate::error::GsmError;
+
+// Largest payload accepted, GitHub caps webhook payloads at 25MB
+const MAX_BODY_SIZE: usize = 25 * 1024 * 1024;
+
+// Repository and branch a push event was made to
+#[derive(Debug, PartialEq)]
+pub struct PushEvent {
+    pub repository_urls: Vec<String>,
+    pub branch: String,
+}
+
+// HTTP status and message sent back to the webhook sender
+#[derive(Debug, PartialEq)]
+pub struct Response {
+    pub status: u16,
+    pub message: String,
+}
+
+impl Response {
+    fn new(status: u16, message: &str) -> Response {
+        Response { status, message: message.to_string() }
+    }
+}
+
+// Checks a GitHub `X-Hub-Signature-256` HMAC or a GitLab `X-Gitlab-Token` against the secret
+pub fn verify_signature(secret: &str, headers: &HashMap<String, String>, body: &[u8]) -> bool {
+    if let Some(signature) = headers.get("x-hub-signature-256") {
+        let signature = match signature.strip_prefix("sha256=").and_then(decode_hex) {
+            Some(signature) => signature,
+            None => return false,
+        };
+        let mut mac = match Hmac::<Sha256>::new_from_slice(secret.as_bytes()) {
+            Ok(mac) => mac,
+            Err(_) => return false,
+        };
+        mac.update(body);
+        return mac.verify_slice(&signature).is_ok();
+    }
+
+    if let Some(token) = headers.get("x-gitlab-token") {
+        // Compare the whole token to not leak how much of it matched
+        return token.len() == secret.len()
+            && token.bytes().zip(secret.bytes()).fold(0, |diff, (a, b)| diff | (a ^ b)) == 0;
+    }
+    false
+}
+
+// Extracts the repository URLs and branch of a GitHub or GitLab push payload.
+// Returns `None` for other events (pings, tag pushes, ...)
+pub fn parse_push_event(headers: &HashMap<String, String>, body: &[u8]) -> Result<Option<PushEvent>, String> {
+    let event = headers.get("x-github-event").or_else(|| headers.get("x-gitlab-event"));
+    if let Some(event) = event {
+        if event != "push" && event != "Push Hook" {
+            return Ok(None);
+        }
+    }
+
+    let payload: Value = serde_json::from_slice(body).map_err(|e| format!("Invalid payload: {}", e))?;
+    let branch = match payload["ref"].as_str().and_then(|r| r.strip_prefix("refs/heads/")) {
+        Some(branch) => branch.to_string(),
+        None => return Ok(None),
+    };
+
+    // GitHub describes the repository under `repository`, GitLab under `project`
+    let keys = ["clone_url", "ssh_url", "git_url", "html_url", "url", "git_http_url", "git_ssh_url", "web_url"];
+    let mut repository_urls = Vec::new();
+    for section in ["repository", "project"] {
+        for key in keys {
+            if let Some(url) = payload[section][key].as_str() {
+                repository_urls.push(url.to_string());
+            }
+        }
+    }
+    if repository_urls.is_empty() {
+        return Err(String::from("Payload has no repository URL"));
+    }
+
+    Ok(Some(PushEvent { repository_urls, branch }))
+}
+
+// Names of the servers whose origin and tracked branch match the push
+pub fn matching_servers(servers: &Servers, event: &PushEvent) -> Vec<String> {
+    let urls = event.repository_urls.iter().map(|url| normalize_remote_url(url)).collect::<Vec<_>>();
+
+    servers.servers.iter()
+        .filter(|server| server.github)
+        .filter(|server| match server.remote_url() {
+            Ok(Some(url)) => urls.contains(&normalize_remote_url(&url)),
+            _ => false,
+        })
+        .filter(|server| server.tracked_branch().ok().flatten().as_deref() == Some(event.branch.as_str()))
+        .map(|server| server.name.clone())
+        .collect()
+}
+
+// Verifies one webhook delivery, the push it carries is answered with 202 and queued for `handle_push`
+pub fn accept_webhook(secret: Option<&str>, headers: &HashMap<String, String>, body: &[u8]) -> (Response, Option<PushEvent>) {
+    if let Some(secret) = secret {
+        if !verify_signature(secret, headers, body) {
+            return (Response::new(401, "Invalid signature"), None);
+        }
+    }
+
+    match parse_push_event(headers, body) {
+        Ok(Some(event)) => (Response::new(202, "Accepted"), Some(event)),
+        Ok(None) => (Response::new(200, "Ignored event"), None),
+        Err(e) => (Response::new(400, &e), None),
+    }
+}
+
+// Runs `update` on the servers matching a push, returns their names
+pub fn handle_push(servers: &mut Servers, event: &PushEvent) -> Vec<String> {
+    let names = matching_servers(servers, event);
+    if names.is_empty() {
+        eprintln!("[WEBHOOK] No server tracks {} on {}", event.branch, event.repository_urls[0]);
+    }
+    for name in &names {
+        eprintln!("[WEBHOOK] Push to {}, updating [{}]", event.branch, name);
+        if let Err(e) = servers.update(name) {
+            eprintln!("[ERROR] [{}] {}", name, e);
+        }
+    }
+    names
+}
+
+// Whether `bind` only accepts connections from this machine
+fn is_loopback(bind: &str) -> bool {
+    bind == "localhost" || bind.parse::<IpAddr>().is_ok_and(|ip| ip.is_loopback())
+}
+
+// Listens for webhook deliveries until the process is stopped. Deliveries are answered as soon as
+// they are verified, so a slow update never makes the sender time out, and the updates run one
+// after the other on the calling thread.
+pub fn serve(servers: &mut Servers, bind: &str, port: u32, secret: Option<&str>) -> Result<(), Box<dyn Error>> {
+    if secret.is_none() && !is_loopback(bind) {
+        return Err(Box::new(GsmError::InvalidArgument(format!(
+            "Refusing to accept unverified webhooks on {}, set --secret or bind to 127.0.0.1", bind
+        ))));
+    }
+    let listener = TcpListener::bind(format!("{}:{}", bind, port))?;
+    if secret.is_none() {
+        eprintln!("[WARNING] No secret set, webhook deliveries are not verified.");
+    }
+    eprintln!("Listening for webhooks on {}:{}, press CTRL-C to stop", bind, port);
+
+    let (sender, receiver) = mpsc::channel();
+    thread::scope(|scope| {
+        scope.spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = match stream {
+                    Ok(stream) => stream,
+                    Err(e) => {
+                        eprintln!("[ERROR] {}", e);
+                        continue;
+                    }
+                };
+
+                let (response, event) = match read_request(&mut stream) {
+                    Ok((method, _, _)) if method != "POST" => (Response::new(405, "Method not allowed"), None),
+                    Ok((_, headers, body)) => accept_webhook(secret, &headers, &body),
+                    Err(e) => (Response::new(400, &e), None),
+                };
+                let reply = format!("HTTP/1.1 {} {}\r\nContent-Type: text/plain\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    response.status,
+                    reason(response.status),
+                    response.message.len(),
+                    response.message
+                );
+                if let Err(e) = stream.write_all(reply.as_bytes()) {
+                    eprintln!("[ERROR] Failed to answer webhook: {}", e);
+                }
+                drop(stream);
+
+                if let Some(event) = event {
+                    if sender.send(event).is_err() {
+                        break;
+                    }
+                }
+            }
+        });
+
+        for event in receiver {
+            handle_push(servers, &event);
+        }
+    });
+    Ok(())
+}
+
+// Method, lowercased headers and body of an HTTP request
+type Request = (String, HashMap<String, String>, Vec<u8>);
+
+fn read_request(stream: &mut TcpStream) -> Result<Request, String> {
+    stream.set_read_timeout(Some(Duration::from_secs(10))).map_err(|e| e.to_string())?;
+    let mut reader = BufReader::new(stream);
+
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line).map_err(|e| e.to_string())?;
+    let method = request_line.split_whitespace().next().ok_or("Empty request")?.to_string();
+
+    let mut headers = HashMap::new();
+    loop {
+        let mut line = String::new();
+        reader.read_line(&mut line).map_err(|e| e.to_string())?;
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        if let Some((key, value)) = line.split_once(':') {
+            headers.insert(key.trim().to_lowercase(), value.trim().to_string());
+        }
+    }
+
+    let length = headers.get("content-length")
+        .map(|length| length.parse::<usize>().map_err(|_| "Invalid Content-Length"))
+        .transpose()?
+        .unwrap_or(0);
+    if length > MAX_BODY_SIZE {
+        return Err(String::from("Payload too large"));
+    }
+    let mut body = vec![0; length];
+    reader.read_exact(&mut body).map_err(|e| e.to_string())?;
+
+    Ok((method, headers, body))
+}
+
+fn reason(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        202 => "Accepted",
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        405 => "Method Not Allowed",
+        _ => "Error",
+    }
+}
+
+fn decode_hex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect()
+}
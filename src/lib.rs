@@ -1,4 +1,6 @@
+#![allow(non_snake_case)]
 pub mod server;
 pub mod commands;
 pub mod utils;
-pub mod github;
\ No newline at end of file
+pub mod github;
+pub mod error;
\ No newline at end of file
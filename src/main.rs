@@ -1,29 +1,75 @@
+#![allow(non_snake_case)]
 use structopt::StructOpt;
 use GServerManager::server::servers::Servers;
 use GServerManager::commands::manager::ServerManager;
 use GServerManager::commands::command::Command;
+use GServerManager::commands::completion::ReplHelper;
+use GServerManager::utils::output;
+use GServerManager::utils::state;
 use rustyline::error::ReadlineError;
-use rustyline::Editor;
+use rustyline::{CompletionType, Config, Editor};
+
+fn server_names(servers: &Option<Servers>) -> Vec<String> {
+    servers.iter()
+        .flat_map(|servers| servers.servers.iter().map(|server| server.name.clone()))
+        .collect()
+}
 
 fn main() {
+    let mut manager = ServerManager::from_args();
+    // Completion scripts call back into the binary from whatever directory the shell is in,
+    // so a `backups/` found there is no reason to move anything
+    let migrate_legacy = !matches!(manager.cmd, Some(Command::Completions { .. }) | Some(Command::Man));
+    if let Err(e) = state::init(manager.state_dir.as_deref(), migrate_legacy) {
+        eprintln!("[ERROR] {}", e);
+        return;
+    }
+    manager.servers = Some(Servers::new(Vec::new()));
+
+    if let Some(servers) = &mut manager.servers {
+        // The prompt and the commands changing servers are the only writer of the state file
+        // for as long as they run, claimed before it is read so no other write is lost
+        let writes = manager.cmd.as_ref().is_none_or(|cmd| !cmd.is_read_only());
+        if writes {
+            if let Err(e) = servers.claim(&state::state_file()) {
+                eprintln!("[ERROR] {}", e);
+                return;
+            }
+        }
+        if let Err(e) = servers.restore() {
+            eprintln!("[ERROR] {}", e);
+            return;
+        }
+    } else {
+        eprintln!("No servers to restore.");
+    }
+
+    // A command given on the command line is run once instead of opening the prompt,
+    // e.g. `GServerManager webhook --port 9000` to run the webhook listener as a service
+    if manager.cmd.is_some() {
+        state::set_command(&std::env::args().skip(1).collect::<Vec<_>>().join(" "));
+        manager.execute();
+        if output::failed() {
+            std::process::exit(1);
+        }
+        return;
+    }
+
     println!("╔════════════════════════════════════════════════════╗");
     println!("║             Welcome to GServerManager              ║");
     println!("╠════════════════════════════════════════════════════╣");
     println!("║   This is a CLI tool for managing WSGI servers.    ║");
     println!("║   Use '-h' command to see the available options.   ║");
     println!("╚════════════════════════════════════════════════════╝");
-    let mut manager = ServerManager::from_args();
-    manager.servers = Some(Servers {
-        servers: Vec::new(),
-    });
-
-    if let Some(servers) = &mut manager.servers {
-        servers.restore();
-    } else {
-        println!("No servers to restore.");
-    }
     
-    let mut rl = Editor::<()>::new();
+    let config = Config::builder()
+        .completion_type(CompletionType::List)
+        .history_ignore_dups(true)
+        .build();
+    let mut rl = Editor::with_config(config);
+    rl.set_helper(Some(ReplHelper::new(server_names(&manager.servers))));
+    // No history yet on the first run
+    let _ = rl.load_history(&state::history_file());
 
     loop {
         let readline = rl.readline(">>> ");
@@ -37,13 +83,16 @@ fn main() {
                     break;
                 }
 
-                let input = format!("{} {}", std::env::args().next().unwrap(), input);
-                let result = Command::from_iter_safe(input.split_whitespace());
+                let result = Command::from_prompt(&input);
 
                 match result {
                     Ok(cmd) => {
+                        state::set_command(input.trim());
                         manager.cmd = Some(cmd);
                         manager.execute();
+                        if let Some(helper) = rl.helper_mut() {
+                            helper.server_names = server_names(&manager.servers);
+                        }
                     }
                     Err(error) => {
                         println!("Invalid command: {}", error);
@@ -64,4 +113,8 @@ fn main() {
             }
         }
     }
+
+    if let Err(e) = rl.save_history(&state::history_file()) {
+        eprintln!("[ERROR] Failed to save history: {}", e);
+    }
 }
@@ -0,0 +1,139 @@
+use std::fmt;
+use std::fs;
+use std::os::unix::fs::symlink;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::Duration;
+use serde::{Serialize, Deserialize};
+use crate::server::server::Server;
+use crate::error::GsmError;
+use crate::server::gunicorn::{start_gunicorn, stop_gunicorn};
+use crate::utils::health::wait_until_healthy;
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Color {
+    Blue,
+    Green,
+}
+
+impl Color {
+    pub fn other(self) -> Color {
+        match self {
+            Color::Blue => Color::Green,
+            Color::Green => Color::Blue,
+        }
+    }
+}
+
+impl fmt::Display for Color {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Color::Blue => write!(f, "blue"),
+            Color::Green => write!(f, "green"),
+        }
+    }
+}
+
+// Two instances of a server alternating between `Server::port` (blue) and `green_port`
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct BlueGreen {
+    pub green_port: u32, // Port of the green instance, the blue one uses the server's port
+    pub active: Color, // Color currently receiving traffic
+    #[serde(default)]
+    pub upstream: Option<PathBuf>, // Proxy config symlinked to the active color's upstream file
+    #[serde(default)]
+    pub proxy_reload: Option<String>, // Command run after the upstream is switched, e.g. `nginx -s reload`
+}
+
+impl BlueGreen {
+    pub fn port(&self, server: &Server, color: Color) -> u32 {
+        match color {
+            Color::Blue => server.port,
+            Color::Green => self.green_port,
+        }
+    }
+}
+
+// Starts the idle color with the code currently in the server directory, waits for it
+// to pass its health check, points the proxy to it and stops the previously active color
+pub fn switch_colors(server: &mut Server) -> Result<(), GsmError> {
+    let mut blue_green = server.blue_green.clone()
+        .ok_or_else(|| GsmError::InvalidState(String::from("Blue/green is not enabled for this server.")))?;
+    let idle = blue_green.active.other();
+
+    let mut next = server.clone();
+    next.running = false;
+    if let Some(next_blue_green) = &mut next.blue_green {
+        next_blue_green.active = idle;
+    }
+    let port = blue_green.port(server, idle);
+
+    eprintln!("Starting the {} instance of [{}] on port {}...", idle, server.name, port);
+    start_gunicorn(&mut next)?;
+
+    if let Err(e) = wait_until_healthy(&server.bind, port, "/", Duration::from_secs(server.timeout as u64)) {
+        stop_gunicorn(&mut next)?;
+        return Err(GsmError::HealthCheck(format!("The {} instance failed its health check, keeping {}: {}", idle, blue_green.active, e)));
+    }
+
+    if let Err(e) = switch_proxy(server, &blue_green, idle, port) {
+        // Point the proxy back to the live color and drop the new instance, so `active` stays accurate
+        if let Some(upstream) = &blue_green.upstream {
+            let active = blue_green.active;
+            if let Err(e) = switch_upstream(server, upstream, active, blue_green.port(server, active)) {
+                eprintln!("[ERROR] Failed to restore the {} upstream: {}", active, e);
+            }
+        }
+        stop_gunicorn(&mut next)?;
+        return Err(e);
+    }
+
+    let mut previous = server.clone();
+    blue_green.active = idle;
+    server.blue_green = Some(blue_green);
+    server.on_command = next.on_command;
+    server.running = true;
+    eprintln!("[{}] is now served by the {} instance on port {}", server.name, idle, port);
+
+    if previous.running {
+        stop_gunicorn(&mut previous)?;
+    }
+    Ok(())
+}
+
+// Sends the proxy to the instance of `color`
+fn switch_proxy(server: &Server, blue_green: &BlueGreen, color: Color, port: u32) -> Result<(), GsmError> {
+    if let Some(upstream) = &blue_green.upstream {
+        switch_upstream(server, upstream, color, port)?;
+    }
+    if let Some(proxy_reload) = &blue_green.proxy_reload {
+        let output = Command::new("sh")
+            .arg("-c")
+            .arg(proxy_reload)
+            .output()?;
+        if !output.status.success() {
+            return Err(GsmError::SpawnFailed {
+                action: String::from("reload the proxy"),
+                stderr: String::from_utf8_lossy(&output.stderr).into_owned(),
+            });
+        }
+    }
+    Ok(())
+}
+
+// Writes the upstream block of a color next to the proxy config and swaps the
+// config symlink to it
+fn switch_upstream(server: &Server, upstream: &Path, color: Color, port: u32) -> Result<(), GsmError> {
+    let host = if server.bind == "0.0.0.0" { "127.0.0.1" } else { server.bind.as_str() };
+    let color_file = PathBuf::from(format!("{}.{}", upstream.display(), color));
+    fs::write(&color_file, format!("upstream {} {{\n    server {}:{};\n}}\n", server.name, host, port))?;
+
+    let next = PathBuf::from(format!("{}.next", upstream.display()));
+    if fs::symlink_metadata(&next).is_ok() {
+        fs::remove_file(&next)?;
+    }
+    symlink(&color_file, &next)?;
+    fs::rename(&next, upstream)?;
+    Ok(())
+}
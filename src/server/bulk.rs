@@ -0,0 +1,136 @@
+use std::fmt;
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+use serde::{Serialize, Serializer};
+use crate::server::server::Server;
+use crate::utils::output::Report;
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Status {
+    Ok,
+    Failed,
+    Skipped,
+}
+
+impl fmt::Display for Status {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Status::Ok => write!(f, "ok"),
+            Status::Failed => write!(f, "failed"),
+            Status::Skipped => write!(f, "skipped"),
+        }
+    }
+}
+
+// Outcome of a bulk operation on one server
+#[derive(Clone, Debug, Serialize)]
+pub struct OperationResult {
+    pub name: String, // Name of the server
+    pub status: Status,
+    pub message: String, // Error for failures, reason for skipped servers
+    #[serde(serialize_with = "serialize_secs")]
+    pub elapsed: Duration, // Time the operation took
+}
+
+fn serialize_secs<S: Serializer>(elapsed: &Duration, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_f64(elapsed.as_secs_f64())
+}
+
+impl OperationResult {
+    pub fn skipped(name: &str, message: String) -> Self {
+        OperationResult {
+            name: name.to_string(),
+            status: Status::Skipped,
+            message,
+            elapsed: Duration::ZERO,
+        }
+    }
+}
+
+// Runs `operation` on every server, at most `parallelism` at a time. Results are
+// returned in the order of `servers` regardless of when each one finished.
+pub fn run_parallel<'a, I, F, E>(servers: I, parallelism: usize, operation: F) -> Vec<OperationResult>
+where
+    I: IntoIterator<Item = &'a mut Server>,
+    F: Fn(&mut Server) -> Result<(), E> + Sync,
+    E: fmt::Display,
+{
+    let queue = Mutex::new(servers.into_iter().enumerate().collect::<Vec<_>>().into_iter());
+    let results = Mutex::new(Vec::new());
+
+    thread::scope(|scope| {
+        for _ in 0..parallelism.max(1) {
+            scope.spawn(|| loop {
+                let next = queue.lock().unwrap().next();
+                let (index, server) = match next {
+                    Some(next) => next,
+                    None => break,
+                };
+
+                let started = Instant::now();
+                let (status, message) = match operation(server) {
+                    Ok(()) => (Status::Ok, String::new()),
+                    Err(e) => (Status::Failed, e.to_string()),
+                };
+                let result = OperationResult {
+                    name: server.name.clone(),
+                    status,
+                    message,
+                    elapsed: started.elapsed(),
+                };
+                results.lock().unwrap().push((index, result));
+            });
+        }
+    });
+
+    let mut results = results.into_inner().unwrap();
+    results.sort_by_key(|(index, _)| *index);
+    results.into_iter().map(|(_, result)| result).collect()
+}
+
+// Results of a bulk operation, `action` being the past tense shown in the summary
+#[derive(Debug, Serialize)]
+pub struct Summary<'a> {
+    pub action: &'a str,
+    pub results: &'a [OperationResult],
+}
+
+impl Report for Summary<'_> {
+    fn print_text(&self) {
+        print_summary(self.action, self.results);
+    }
+}
+
+// Reports a single server like the other commands do, or a summary table for several
+pub fn print_summary(action: &str, results: &[OperationResult]) {
+    if let [result] = results {
+        match result.status {
+            Status::Ok => println!("Successfully {} [{}]", action, result.name),
+            Status::Failed => eprintln!("[ERROR] {}", result.message),
+            Status::Skipped => println!("[{}] {}", result.name, result.message),
+        }
+        return;
+    }
+
+    let count = |status: Status| results.iter().filter(|result| result.status == status).count();
+    println!("[INFO] {} {} | {} failed | {} skipped\n",
+        count(Status::Ok),
+        action,
+        count(Status::Failed),
+        count(Status::Skipped)
+    );
+
+    let width = results.iter().map(|result| result.name.len()).max().unwrap_or(0).max("SERVER".len());
+    println!("{:<width$}  {:<7}  {:>6}  DETAILS", "SERVER", "STATUS", "TIME", width = width);
+    for result in results {
+        println!("{:<width$}  {:<7}  {:>5.1}s  {}",
+            result.name,
+            result.status.to_string(),
+            result.elapsed.as_secs_f64(),
+            result.message.lines().next().unwrap_or(""),
+            width = width
+        );
+    }
+}
@@ -0,0 +1,61 @@
+use std::collections::BTreeMap;
+use std::process::Command;
+use crate::server::server::Server;
+use crate::error::GsmError;
+use crate::utils::build::{BuildStep, run_build_steps};
+
+// Runs `manage.py` with the given arguments in the server directory and virtualenv,
+// attached to the terminal so interactive commands like `createsuperuser` work
+pub fn manage(server: &Server, args: &[String]) -> Result<(), GsmError> {
+    if server.framework != "django" {
+        return Err(GsmError::InvalidArgument(format!("[{}] is not a Django server.", server.name)));
+    }
+    let path = server.original_dir.join(&server.path);
+    if !path.join("manage.py").is_file() {
+        return Err(GsmError::InvalidServerDir(path));
+    }
+
+    let status = Command::new(server.venv_bin("python"))
+        .arg("manage.py")
+        .args(args)
+        .envs(server.command_environment()?)
+        .current_dir(&path)
+        .status()?;
+    if !status.success() {
+        return Err(GsmError::Exited {
+            action: format!("run `manage.py {}`", args.join(" ")),
+            status,
+        });
+    }
+    Ok(())
+}
+
+// Steps run after every update or deploy of a Django server, once the build is done
+pub fn deploy_steps(server: &Server) -> Vec<BuildStep> {
+    if server.framework != "django" {
+        return Vec::new();
+    }
+
+    let step = |args: &str| BuildStep {
+        command: format!("{} manage.py {}", server.venv_bin("python"), args),
+        dir: None,
+        env: BTreeMap::new(),
+        timeout: None,
+        patterns: Vec::new(),
+    };
+    vec![
+        step("migrate --noinput"),
+        step("collectstatic --noinput"),
+    ]
+}
+
+// Applies migrations and collects static files, with their output appended to the build log
+pub fn run_deploy_steps(server: &Server) -> Result<(), GsmError> {
+    let steps = deploy_steps(server);
+    if steps.is_empty() {
+        return Ok(());
+    }
+    run_build_steps(server, &steps, None, &server.build_log())
+        .map(|_| ())
+        .map_err(|e| GsmError::Build(format!("Django deploy step failed: {}, see {} for details.", e, server.build_log().display())))
+}
@@ -1,115 +1,94 @@
 use std::fs;
-use std::env;
-use std::error::Error;
 use std::process::Command;
 use crate::server::server::Server;
+use crate::error::GsmError;
+use crate::utils::state;
 
-fn get_app_string(server: &mut Server, framework: &str) -> Result<String, Box<dyn Error>> {
+// Command line starting gunicorn through `sh -c`, every path and the app are quoted since
+// factory apps like `main:create_app()` aren't valid shell words
+pub fn get_gunicorn_command(server: &Server, framework: &str, app: &str, absolute_log_path: &str) -> Result<String, GsmError> {
     match framework {
-        "flask" | "fastapi" => {
-            if server.path.join("main.py").exists() {
-                Ok(String::from("main:app"))
-            } else {
-                Ok(String::from("app:app"))
-            }
-        },
-        "django" => {
-            let wsgi_files = fs::read_dir(&server.path)?.filter_map(|entry| {
-                if let Ok(entry) = entry {
-                    let path = entry.path();
-                    if path.is_file() && path.extension().and_then(|ext| ext.to_str()) == Some("wsgi") {
-                        Some(entry.file_name().to_string_lossy().into_owned())
-                    } else {
-                        None
-                    }
-                } else {
-                    None
-                }
-            }).collect::<Vec<_>>();
-
-            if let Some(wsgi_file) = wsgi_files.first() {
-                Ok(wsgi_file.clone())
-            } else {
-                Err("No .wsgi files found.".into())
-            }
-        },
-        _ => Err("Unsupported framework.".into()),
-    }
-}
-
-fn get_gunicorn_command(server: &mut Server, framework: &str, app: &str, absolute_log_path: &str) -> Result<String, Box<dyn Error>> {
-    match framework {
-        "flask" | "fastapi" => Ok(format!("gunicorn --bind={}:{} --timeout={} --daemon --access-logfile {}/{}.log --error-logfile {}/{}.log --pid {}.pid --workers={} --worker-class=gevent {}",
-                                            server.bind,
-                                            server.port,
+        "flask" | "fastapi" | "django" => Ok(format!("{} --bind={}:{} --timeout={} --daemon --access-logfile {} --error-logfile {} --chdir {} --pid {} --workers={} --worker-class=gevent {}",
+                                            shell_quote(&server.venv_bin("gunicorn")),
+                                            shell_quote(&server.bind),
+                                            server.serving_port(),
                                             server.timeout,
-                                            absolute_log_path,
-                                            server.name,
-                                            absolute_log_path,
-                                            server.name,
-                                            server.name,
+                                            shell_quote(&format!("{}/{}.log", absolute_log_path, server.name)),
+                                            shell_quote(&format!("{}/{}.log", absolute_log_path, server.name)),
+                                            shell_quote(&server.original_dir.join(&server.path).display().to_string()),
+                                            shell_quote(&server.pid_file().display().to_string()),
                                             server.workers,
-                                            app
+                                            shell_quote(app)
                                         )),
-        "django" => Ok(format!("gunicorn --bind={}:{} --timeout={} --daemon --access-logfile {}/{}.log --error-logfile {}/{}.log --pid {}.pid --worker-type=gevent {}",
-                                            server.bind,
-                                            server.port,
-                                            server.timeout,
-                                            absolute_log_path,
-                                            server.name,
-                                            absolute_log_path,
-                                            server.name,
-                                            server.name,
-                                            app
-                                        )),
-        _ => Err("Unsupported framework".into()),
+        _ => Err(GsmError::InvalidArgument(format!("Unsupported framework {}", framework))),
     }
 }
 
-pub fn start_gunicorn(server: &mut Server) -> Result<(), Box<dyn Error>> {
-    let framework = server.framework.clone();
-    let app = get_app_string(server, &framework)?;
-    let absolute_log_path = fs::canonicalize(&server.log_path)?.to_str().ok_or("Failed to convert path to string")?.to_owned();
+// Single-quotes `value` for `sh`, a quote inside it closes the string, is escaped and reopens it
+fn shell_quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', "'\\''"))
+}
 
-    // navigate to server.path
-    env::set_current_dir(&server.path)?;
+pub fn start_gunicorn(server: &mut Server) -> Result<(), GsmError> {
+    let framework = server.framework.clone();
+    let app = server.app_string()?;
+    let absolute_log_path = fs::canonicalize(server.original_dir.join(&server.log_path))?.display().to_string();
+    fs::create_dir_all(state::pids_dir())?;
 
     let gunicorn_command = get_gunicorn_command(server, &framework, &app, &absolute_log_path)?;
     server.on_command = gunicorn_command.clone();
     let output = Command::new("sh")
+        .envs(server.environment()?)
         .arg("-c")
         .arg(&gunicorn_command)
+        .current_dir(server.original_dir.join(&server.path))
         .output()?;
 
     if output.status.success() {
         server.running = true;
-        println!("Successfully started [{}]", server.name);
     } else {
-        let error_message = String::from_utf8_lossy(&output.stderr);
-        return Err(format!("Failed to start [{}]: {}", server.name, error_message).into());
+        return Err(GsmError::SpawnFailed {
+            action: format!("start [{}]", server.name),
+            stderr: String::from_utf8_lossy(&output.stderr).into_owned(),
+        });
     }
 
-    env::set_current_dir(&server.original_dir)?;
-
     Ok(())
 }
 
 
-pub fn stop_gunicorn(server: &mut Server) -> Result<(), Box<dyn Error>> {
-    env::set_current_dir(&server.path)?;
+pub fn stop_gunicorn(server: &mut Server) -> Result<(), GsmError> {
     let output = Command::new("pkill")
         .arg("-F")
-        .arg(format!("{}.pid", server.name))
+        .arg(server.pid_file())
+        .current_dir(server.original_dir.join(&server.path))
         .output()?;
 
     if output.status.success() {
         server.running = false;
-        println!("Successfully stopped [{}]", server.name);
     } else {
-        let error_message = String::from_utf8_lossy(&output.stderr);
-        return Err(format!("Failed to stop [{}]: {}", server.name, error_message).into());
+        return Err(GsmError::SpawnFailed {
+            action: format!("stop [{}]", server.name),
+            stderr: String::from_utf8_lossy(&output.stderr).into_owned(),
+        });
+    }
+    Ok(())
+}
+
+// Gracefully reloads the workers by sending SIGHUP to the gunicorn master
+pub fn reload_gunicorn(server: &mut Server) -> Result<(), GsmError> {
+    let output = Command::new("pkill")
+        .arg("-HUP")
+        .arg("-F")
+        .arg(server.pid_file())
+        .current_dir(server.original_dir.join(&server.path))
+        .output()?;
+
+    if !output.status.success() {
+        return Err(GsmError::SpawnFailed {
+            action: format!("reload [{}]", server.name),
+            stderr: String::from_utf8_lossy(&output.stderr).into_owned(),
+        });
     }
-    env::set_current_dir(&server.original_dir)?;
-    env::set_current_dir(&server.original_dir)?;
     Ok(())
-}
\ No newline at end of file
+}
@@ -1,4 +1,11 @@
+#[allow(clippy::module_inception)]
 pub mod server;
 pub mod servers;
 pub mod gunicorn;
-pub mod redis;
\ No newline at end of file
+pub mod redis;
+pub mod releases;
+pub mod blue_green;
+pub mod django;
+pub mod bulk;
+pub mod reports;
+pub mod snapshots;
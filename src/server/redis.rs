@@ -1,19 +1,17 @@
 use std::fs;
-use std::env;
-use std::error::Error;
 use std::process::Command;
 use crate::server::server::Server;
+use crate::error::GsmError;
 
-pub fn start_redis(server: &mut Server) -> Result<(), Box<dyn Error>> {
+pub fn start_redis(server: &mut Server) -> Result<(), GsmError> {
     // redis-server ./configs/redis.conf
-    let absolute_log_path = fs::canonicalize(&server.log_path)?.to_str().ok_or("Failed to convert path to string")?.to_owned();
-    env::set_current_dir(&server.original_dir)?;
-    let redis_command = if server.path.join("redis.conf").exists() {
+    let absolute_log_path = fs::canonicalize(server.original_dir.join(&server.log_path))?.display().to_string();
+    let redis_command = if server.original_dir.join(&server.path).join("redis.conf").exists() {
         format!("redis-server {}/redis.conf --daemonize yes --bind {} --port {} --timeout {} --dir ./{} --logfile {}/{}.log", 
             server.path.display(),
             server.bind,
-            server.port.to_string(),
-            server.timeout.to_string(),
+            server.port,
+            server.timeout,
             server.path.display(),
             absolute_log_path,
             server.name,
@@ -22,44 +20,48 @@ pub fn start_redis(server: &mut Server) -> Result<(), Box<dyn Error>> {
         format!(
             "redis-server --daemonize yes --bind {} --port {} --timeout {} --dir ./{} --logfile {}/{}.log",
             server.bind,
-            server.port.to_string(),
-            server.timeout.to_string(),
+            server.port,
+            server.timeout,
             server.path.display(),
             absolute_log_path,
             server.name,
         )
     };
     let output = Command::new("sh")
+        .envs(server.environment()?)
         .arg("-c")
         .arg(&redis_command)
+        .current_dir(&server.original_dir)
         .output()?;
 
     if output.status.success() {
         server.running = true;
-        println!("Successfully started [{}]", server.name);
     } else {
-        let error_message = String::from_utf8_lossy(&output.stderr);
-        return Err(format!("Failed to start [{}]: {}", server.name, error_message).into());
+        return Err(GsmError::SpawnFailed {
+            action: format!("start [{}]", server.name),
+            stderr: String::from_utf8_lossy(&output.stderr).into_owned(),
+        });
     }
 
     Ok(())
 }
 
 
-pub fn stop_redis(server: &mut Server) -> Result<(), Box<dyn Error>> {
-    env::set_current_dir(&server.original_dir)?;
+pub fn stop_redis(server: &mut Server) -> Result<(), GsmError> {
     let output = Command::new("redis-cli")
                 .arg("-p")
-                .arg(server.port.to_string())
+                .arg(server.running_address.as_ref().map_or(server.port, |(_, port)| *port).to_string())
                 .arg("shutdown")
+                .current_dir(&server.original_dir)
                 .output()?;
         
     if output.status.success() {
         server.running = false;
-        println!("Successfully stopped [{}]", server.name);
     } else {
-        let error_message = String::from_utf8_lossy(&output.stderr);
-        return Err(format!("Failed to stop [{}]: {}", server.name, error_message).into());
+        return Err(GsmError::SpawnFailed {
+            action: format!("stop [{}]", server.name),
+            stderr: String::from_utf8_lossy(&output.stderr).into_owned(),
+        });
     }
 
     Ok(())
@@ -0,0 +1,255 @@
+use std::fs;
+use std::os::unix::fs::symlink;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use crate::server::server::{Server, short_sha};
+use crate::error::GsmError;
+use crate::server::gunicorn::{start_gunicorn, stop_gunicorn};
+use crate::server::django::run_deploy_steps;
+use crate::github::utils::{git_clone, git_checkout, git_remote_update, git_remote_url, git_rev_parse};
+use crate::utils::deps::{install_commands, sync_dependencies, DEPENDENCY_FILES};
+use crate::utils::health::{free_port, wait_until_healthy};
+
+// Layout under the release root:
+//   repo/                    bare mirror of the origin, fetched on every deploy
+//   releases/<time>-<sha>/   one checkout per deploy, with its own `.venv` when it declares dependencies
+//   current -> releases/...  symlink to the live release, which `Server::path` points to
+
+// Virtualenv of a release, inside its checkout
+pub(crate) const RELEASE_VENV: &str = ".venv";
+
+// Switches a git-backed server to the releases layout, its current commit becomes the first release
+pub fn enable_releases(server: &mut Server, root: &Path, keep: u32) -> Result<(), GsmError> {
+    if server.release_root.is_some() {
+        return Err(GsmError::InvalidState(String::from("Releases are already enabled for this server.")));
+    }
+    if !server.github {
+        return Err(GsmError::InvalidState(String::from("Releases require the server directory to be a git repository.")));
+    }
+    if server.name.to_lowercase().contains("redis-server") {
+        return Err(GsmError::InvalidArgument(String::from("Releases are not supported for Redis servers.")));
+    }
+
+    let root = server.original_dir.join(root);
+    let source = server.original_dir.join(&server.path);
+    let head = git_rev_parse("HEAD", &source)
+        .map_err(GsmError::Git)?
+        .ok_or_else(|| GsmError::Git(String::from("Repository has no commits yet.")))?;
+    let url = git_remote_url(&source).map_err(GsmError::Git)?.unwrap_or_else(|| source.display().to_string());
+
+    fs::create_dir_all(root.join("releases"))?;
+    git_clone(&url, &root.join("repo"), true).map_err(GsmError::Git)?;
+    let release = create_release(&root, &head)?;
+    switch_current(&root, &release)?;
+
+    server.path = root.join("current");
+    server.release_root = Some(root);
+    server.keep_releases = keep;
+    server.deployed_commit = Some(head);
+    eprintln!("Releases enabled for [{}], restart it to serve from {}", server.name, server.path.display());
+    Ok(())
+}
+
+// Checks out `rev` into a new release, builds and health-checks it, then makes it current
+pub fn deploy_release(server: &mut Server, rev: &str) -> Result<(), GsmError> {
+    let root = server.release_root.clone()
+        .ok_or_else(|| GsmError::InvalidState(String::from("Releases are not enabled for this server.")))?;
+    let repo = root.join("repo");
+
+    if let Err(e) = git_remote_update(&repo) {
+        eprintln!("Failed to fetch from origin, deploying from the cached repository: {}", e);
+    }
+    let sha = git_rev_parse(rev, &repo)
+        .map_err(GsmError::Git)?
+        .ok_or_else(|| GsmError::InvalidArgument(format!("Unknown ref '{}'", rev)))?;
+
+    let release = create_release(&root, &sha)?;
+    if let Err(e) = prepare_release(server, &release) {
+        fs::remove_dir_all(&release)?;
+        return Err(GsmError::Build(format!("Release {} rejected: {}", short_sha(&sha), e)));
+    }
+
+    switch_release(server, &root, &release, &sha)?;
+    eprintln!("Deployed {} ({}) to [{}]", rev, short_sha(&sha), server.name);
+    prune_releases(&root, server.keep_releases)?;
+    Ok(())
+}
+
+// Points `current` back to the release before the live one
+pub fn rollback_release(server: &mut Server) -> Result<(), GsmError> {
+    let root = server.release_root.clone()
+        .ok_or_else(|| GsmError::InvalidState(String::from("Releases are not enabled for this server.")))?;
+    let releases = list_releases(&root)?;
+    let live = fs::read_link(root.join("current"))?;
+
+    let index = releases.iter()
+        .position(|release| *release == live)
+        .ok_or_else(|| GsmError::InvalidState(String::from("Live release not found.")))?;
+    if index == 0 {
+        return Err(GsmError::InvalidState(String::from("No previous release to roll back to.")));
+    }
+    let target = &releases[index - 1];
+    let sha = git_rev_parse("HEAD", target)
+        .map_err(GsmError::Git)?
+        .ok_or_else(|| GsmError::Git(String::from("Failed to read the release commit.")))?;
+
+    switch_release(server, &root, target, &sha)?;
+    eprintln!("Rolled back [{}] to {}", server.name, target.display());
+    Ok(())
+}
+
+// Makes `release` current and puts it into service. If that fails, `current` points to the
+// previous release again and the recorded commits are left as they were.
+fn switch_release(server: &mut Server, root: &Path, release: &Path, sha: &str) -> Result<(), GsmError> {
+    let live = fs::read_link(root.join("current"))?;
+    let (deployed, previous) = (server.deployed_commit.clone(), server.previous_commit.clone());
+    let venv = server.active_venv();
+    let was_running = server.running;
+
+    switch_current(root, release)?;
+    server.record_deploy(deployed.as_deref().unwrap_or_default(), sha);
+    if let Err(e) = activate_release(server, venv) {
+        eprintln!("Failed to activate {}, switching back to {}...", release.display(), live.display());
+        switch_current(root, &live)?;
+        server.deployed_commit = deployed;
+        server.previous_commit = previous;
+        // A failed restart can leave the server stopped
+        if was_running && !server.running {
+            if let Err(e) = server.start() {
+                eprintln!("[ERROR] Failed to start [{}] again: {}", server.name, e);
+            }
+        }
+        return Err(e);
+    }
+    Ok(())
+}
+
+// Puts the new live release into service. Gunicorn workers fork from a master that keeps its
+// interpreter, so a release running in another virtualenv needs a restart instead of a reload.
+fn activate_release(server: &mut Server, previous_venv: Option<PathBuf>) -> Result<(), GsmError> {
+    if !server.running {
+        return Ok(());
+    }
+
+    let resolve = |venv: Option<PathBuf>| venv.map(|venv| fs::canonicalize(&venv).unwrap_or(venv));
+    if server.blue_green.is_none() && resolve(server.active_venv()) != resolve(previous_venv) {
+        server.restart()
+    } else {
+        server.activate()
+    }
+}
+
+// Release directories, oldest first
+pub fn list_releases(root: &Path) -> Result<Vec<PathBuf>, GsmError> {
+    let mut releases = fs::read_dir(root.join("releases"))?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.is_dir())
+        .collect::<Vec<_>>();
+    releases.sort();
+    Ok(releases)
+}
+
+fn create_release(root: &Path, sha: &str) -> Result<PathBuf, GsmError> {
+    // Nanoseconds, so two deploys in the same second get different releases that still sort by time
+    let timestamp = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_nanos();
+    let release = root.join("releases").join(format!("{}-{}", timestamp, short_sha(sha)));
+    if release.exists() {
+        return Err(GsmError::InvalidState(format!("Release {} already exists.", release.display())));
+    }
+
+    git_clone(&root.join("repo").display().to_string(), &release, false).map_err(GsmError::Git)?;
+    if let Err(e) = git_checkout(sha, &release) {
+        fs::remove_dir_all(&release)?;
+        return Err(GsmError::Git(format!("Failed to check out {}: {}", short_sha(sha), e)));
+    }
+    Ok(release)
+}
+
+// Installs the release's dependencies into its own virtualenv, builds it and runs the Django deploy steps,
+// then boots it on a free port and waits for it to answer before it can replace the live release
+fn prepare_release(server: &Server, release: &Path) -> Result<(), GsmError> {
+    let mut candidate = server.clone();
+    candidate.name = format!("{}-candidate", server.name);
+    candidate.path = release.to_path_buf();
+    candidate.release_root = None;
+    candidate.blue_green = None;
+    candidate.running = false;
+
+    create_release_venv(&mut candidate)?;
+
+    // A fresh checkout has no build artifacts, so the whole pipeline runs
+    candidate.build(None)?;
+    run_deploy_steps(&candidate)?;
+
+    candidate.bind = String::from("127.0.0.1");
+    candidate.port = free_port().map_err(GsmError::InvalidState)?;
+    eprintln!("Health checking release on port {}...", candidate.port);
+    start_gunicorn(&mut candidate)?;
+
+    let healthy = wait_until_healthy(&candidate.bind, candidate.port, "/", Duration::from_secs(server.timeout as u64));
+    stop_gunicorn(&mut candidate)?;
+    healthy.map_err(GsmError::HealthCheck)?;
+    Ok(())
+}
+
+// Gives a release that declares dependencies its own virtualenv with all of them installed, so a
+// release that fails its health check never changes the packages the live one is importing.
+// Releases without dependency files run in the server's virtualenv.
+fn create_release_venv(candidate: &mut Server) -> Result<(), GsmError> {
+    let release = candidate.original_dir.join(&candidate.path);
+    let dependencies = DEPENDENCY_FILES.iter().copied().filter(|file| release.join(file).exists()).collect::<Vec<_>>();
+    if dependencies.is_empty() {
+        return Ok(());
+    }
+
+    let venv = release.join(RELEASE_VENV);
+    eprintln!("Creating the release virtualenv and installing {}...", dependencies.join(", "));
+    let mut command = Command::new(candidate.venv_bin("python3"));
+    command.args(["-m", "venv"]);
+    // Without a virtualenv the server runs on the system packages, gunicorn included
+    if candidate.venv.is_none() {
+        command.arg("--system-site-packages");
+    }
+    let output = command.arg(&venv).output().map_err(|e| GsmError::SpawnFailed {
+        action: String::from("create the release virtualenv"),
+        stderr: e.to_string(),
+    })?;
+    if !output.status.success() {
+        return Err(GsmError::SpawnFailed {
+            action: String::from("create the release virtualenv"),
+            stderr: String::from_utf8_lossy(&output.stderr).into_owned(),
+        });
+    }
+
+    let pip = venv.join("bin").join("pip").display().to_string();
+    let mut commands = install_commands(&dependencies, &release, Some(&pip));
+    commands.push(vec![pip, String::from("install"), String::from("gunicorn"), String::from("gevent")]);
+
+    candidate.venv = Some(venv);
+    sync_dependencies(candidate, &commands)
+        .map_err(|e| GsmError::Build(format!("Failed to install dependencies: {}", e)))
+}
+
+// Replaces the `current` symlink with a rename so it never points nowhere
+fn switch_current(root: &Path, release: &Path) -> Result<(), GsmError> {
+    let next = root.join("current.next");
+    if fs::symlink_metadata(&next).is_ok() {
+        fs::remove_file(&next)?;
+    }
+    symlink(release, &next)?;
+    fs::rename(&next, root.join("current"))?;
+    Ok(())
+}
+
+// Removes the oldest releases beyond `keep`, never the live one
+fn prune_releases(root: &Path, keep: u32) -> Result<(), GsmError> {
+    let live = fs::read_link(root.join("current"))?;
+    let releases = list_releases(root)?;
+    let excess = releases.len().saturating_sub(keep.max(1) as usize);
+
+    for release in releases.iter().take(excess).filter(|release| **release != live) {
+        fs::remove_dir_all(release)?;
+    }
+    Ok(())
+}
@@ -0,0 +1,195 @@
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use serde::Serialize;
+use crate::server::server::short_sha;
+use crate::server::blue_green::Color;
+use crate::utils::build::BuildStep;
+use crate::utils::hooks::{Hook, HookKind};
+use crate::utils::output::Report;
+use crate::github::utils::GitStatus;
+
+// One server as shown by `list`
+#[derive(Debug, Serialize)]
+pub struct ServerInfo {
+    pub name: String,
+    pub running: bool,
+    pub bind: String,
+    pub port: u32, // Port currently serving traffic
+    pub color: Option<Color>, // Active color of blue/green servers
+    pub workers: u32,
+    pub timeout: u32,
+    pub log_path: PathBuf,
+    pub commit: Option<String>, // Short hash of the commit being served
+    pub env: BTreeMap<String, String>, // Masked values
+    pub tags: Vec<String>,
+    pub depends_on: Vec<String>,
+    pub restart_required: bool, // Running with a bind or port restored from a snapshot not applied yet
+}
+
+impl Report for Vec<ServerInfo> {
+    fn print_text(&self) {
+        println!("[INFO] Listing all available servers");
+        println!("[INFO] [*]: Running | [ ]: Not running \n");
+        for server in self {
+            let symbol = if server.running { "*" } else { " " };
+            let env = server.env.iter()
+                .map(|(key, value)| format!("{}={}", key, value))
+                .collect::<Vec<_>>()
+                .join(", ");
+            let color = match &server.color {
+                Some(color) => format!(" ({})", color),
+                None => String::new(),
+            };
+            let restart = if server.restart_required { " (restart required)" } else { "" };
+            println!("[{}] Name: {} | Address: {}:{}{}{} | Workers: {} | Timeout: {}s | Log Path: {} | Commit: {} | Env: [{}] | Tags: [{}] |",
+                symbol,
+                server.name,
+                server.bind,
+                server.port,
+                color,
+                restart,
+                server.workers,
+                server.timeout,
+                server.log_path.display(),
+                server.commit.as_deref().unwrap_or("-"),
+                env,
+                server.tags.join(", ")
+            );
+        }
+    }
+}
+
+// Contents of a server's log file, shown by `monitor`
+#[derive(Debug, Serialize)]
+pub struct LogsReport {
+    pub name: String,
+    pub log: String,
+}
+
+impl Report for LogsReport {
+    fn print_text(&self) {
+        println!("Successfully retrieved server logs.");
+        println!("{}", self.log);
+    }
+}
+
+// Environment of a server, shown by `env list`
+#[derive(Debug, Serialize)]
+pub struct EnvReport {
+    pub name: String,
+    pub env_file: Option<PathBuf>,
+    pub vars: BTreeMap<String, String>, // Masked unless `--show` is given
+}
+
+impl Report for EnvReport {
+    fn print_text(&self) {
+        if let Some(env_file) = &self.env_file {
+            println!("[INFO] Env file: {}", env_file.display());
+        }
+        for (key, value) in &self.vars {
+            println!("{}={}", key, value);
+        }
+    }
+}
+
+// Lifecycle hooks of a server, shown by `hooks list`
+#[derive(Debug, Serialize)]
+pub struct HooksReport {
+    pub name: String,
+    pub hooks: BTreeMap<HookKind, Hook>,
+}
+
+impl Report for HooksReport {
+    fn print_text(&self) {
+        if self.hooks.is_empty() {
+            println!("[INFO] No hooks defined for [{}]", self.name);
+        }
+        for (kind, hook) in &self.hooks {
+            println!("[{}] `{}` | Timeout: {}s |", kind, hook.command, hook.timeout);
+        }
+    }
+}
+
+// Build pipeline of a server, shown by `build list`
+#[derive(Debug, Serialize)]
+pub struct BuildStepsReport {
+    pub name: String,
+    pub default: bool, // Whether the default pipeline is used
+    pub steps: Vec<BuildStep>,
+}
+
+impl Report for BuildStepsReport {
+    fn print_text(&self) {
+        if self.default {
+            println!("[INFO] No build steps defined for [{}], using the default pipeline", self.name);
+        }
+        for (index, step) in self.steps.iter().enumerate() {
+            println!("[{}] `{}` | Dir: {} | Timeout: {} | Patterns: [{}] |",
+                index,
+                step.command,
+                step.dir.as_ref().map(|dir| dir.display().to_string()).unwrap_or_else(|| String::from(".")),
+                step.timeout.map(|timeout| format!("{}s", timeout)).unwrap_or_else(|| String::from("none")),
+                step.patterns.join(", ")
+            );
+        }
+    }
+}
+
+// Release directories of a server, shown by `releases list`
+#[derive(Debug, Serialize)]
+pub struct ReleasesReport {
+    pub name: String,
+    pub keep: u32,
+    pub live: PathBuf,
+    pub releases: Vec<PathBuf>, // Oldest first
+}
+
+impl Report for ReleasesReport {
+    fn print_text(&self) {
+        println!("[INFO] Listing releases of [{}] (keeping {})", self.name, self.keep);
+        println!("[INFO] [*]: Live | [ ]: Available for rollback \n");
+        for release in &self.releases {
+            let symbol = if *release == self.live { "*" } else { " " };
+            println!("[{}] {}", symbol, release.display());
+        }
+    }
+}
+
+// Repository state of a server, shown by `git_status`
+#[derive(Debug, Serialize)]
+pub struct GitStatusReport {
+    pub name: String,
+    #[serde(flatten)]
+    pub status: GitStatus,
+}
+
+impl Report for GitStatusReport {
+    fn print_text(&self) {
+        let status = &self.status;
+        println!("[INFO] Git status of [{}]", self.name);
+        println!("Branch: {}", status.branch.as_deref().unwrap_or("(detached HEAD)"));
+        println!("Commit: {} {}", short_sha(&status.head), status.subject);
+        match &status.upstream {
+            Some(upstream) => println!("Upstream: {} | Ahead: {} | Behind: {}", upstream, status.ahead, status.behind),
+            None => println!("Upstream: none"),
+        }
+
+        if status.dirty.is_empty() {
+            println!("Working tree clean");
+        } else {
+            println!("Uncommitted changes:");
+            for line in &status.dirty {
+                println!("    {}", line);
+            }
+        }
+
+        if status.incoming.is_empty() {
+            println!("Up to date, `update` would not change any file");
+        } else {
+            println!("Files changed by `update`:");
+            for file in &status.incoming {
+                println!("    {}", file);
+            }
+        }
+    }
+}
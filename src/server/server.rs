@@ -1,11 +1,23 @@
 use std::env;
-use std::path::PathBuf;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
 use std::process::Command;
-use std::error::Error;
-use crate::utils::build::{contains_compiled_files, compile_and_install_project, run_cmake};
-use crate::github::utils::{git_pull, git_diff_name_only, initialize_git_repository, add_remote_origin};
-use crate::server::gunicorn::{start_gunicorn, stop_gunicorn};
+use std::time::Duration;
+use crate::utils::build::{BuildStep, default_build_steps, run_build_steps};
+use crate::github::utils::{git_fetch, git_checkout, git_fast_forward, git_rev_parse, git_diff_name_only, git_current_branch, git_remote_url, git_remote_update, git_is_ancestor, git_status, git_commit_count, GitStatus, initialize_git_repository, add_remote_origin, is_repository_root};
+use crate::server::gunicorn::{start_gunicorn, stop_gunicorn, reload_gunicorn};
 use crate::server::redis::{start_redis, stop_redis};
+use crate::server::releases::{deploy_release, rollback_release, RELEASE_VENV};
+use crate::server::blue_green::{BlueGreen, switch_colors};
+use crate::server::django::run_deploy_steps;
+use crate::github::poller::AutoUpdate;
+use crate::error::GsmError;
+use crate::utils::dotenv::parse_env_file;
+use crate::utils::detect::detect_app;
+use crate::utils::hooks::{Hook, HookKind, run_hook};
+use crate::utils::health::{wait_until_healthy, wait_until_listening};
+use crate::utils::state;
+use crate::utils::deps::{changed_dependency_files, install_commands, sync_dependencies};
 
 #[derive(Clone, Debug)]
 pub struct Server {
@@ -20,167 +32,608 @@ pub struct Server {
     pub running: bool, // Whether or not the server is currently running
     pub framework: String, // The Python framework used, default Flask
     pub original_dir: PathBuf, // The original directory when the application was started
-    pub on_command: String
+    pub on_command: String,
+    pub env: BTreeMap<String, String>, // Environment variables set for the server process
+    pub env_file: Option<PathBuf>, // Optional `.env` file loaded before `env`
+    pub venv: Option<PathBuf>, // Python virtualenv used to run the server, defaults to the PATH
+    pub build_steps: Vec<BuildStep>, // Build pipeline run on update, cmake/make for C++ projects if empty
+    pub deployed_commit: Option<String>, // Commit checked out by the last update or deploy
+    pub previous_commit: Option<String>, // Commit that was live before the last deploy, used by rollback
+    pub branch: Option<String>, // Branch followed by `update`, kept while a tag or commit is deployed
+    pub release_root: Option<PathBuf>, // Root of the releases layout, `path` points to its `current` symlink when set
+    pub keep_releases: u32, // Number of releases kept for rollback
+    pub blue_green: Option<BlueGreen>, // Blue/green instances, deploys switch traffic between them
+    pub auto_update: Option<AutoUpdate>, // Polling settings for automatic updates
+    pub app: Option<String>, // Entry point as `module:callable`, detected from the project if unset
+    pub hooks: BTreeMap<HookKind, Hook>, // Shell commands run before/after start, stop and update
+    pub tags: Vec<String>, // Tags used to select groups of servers in bulk commands
+    pub depends_on: Vec<String>, // Servers started before this one and stopped after it
+    pub running_address: Option<(String, u32)>, // Bind and port the process still serves after a snapshot changed them, until it is restarted
 }
 
 impl Server {
+    // Flask server with the default settings, other settings are set with struct update syntax:
+    // `Server { workers: 8, ..Server::new(name, path, port) }`
+    pub fn new(name: &str, path: PathBuf, port: u32) -> Server {
+        Server {
+            name: name.to_string(),
+            path: path.clone(),
+            bind: String::from("0.0.0.0"),
+            port,
+            workers: 4,
+            timeout: 30,
+            log_path: path,
+            github: false,
+            running: false,
+            framework: String::from("flask"),
+            original_dir: env::current_dir().unwrap_or_default(),
+            on_command: String::new(),
+            env: BTreeMap::new(),
+            env_file: None,
+            venv: None,
+            build_steps: Vec::new(),
+            deployed_commit: None,
+            previous_commit: None,
+            branch: None,
+            release_root: None,
+            keep_releases: 5,
+            blue_green: None,
+            auto_update: None,
+            app: None,
+            hooks: BTreeMap::new(),
+            tags: Vec::new(),
+            depends_on: Vec::new(),
+            running_address: None,
+        }
+    }
+
     pub fn is_valid(&self) -> bool {
-        // Checks if self.path contains app.py or main.py
-        if self.path.join("main.py").exists() || self.path.join("app.py").exists() {
-            return true;
+        // Checks if an entry point is set or can be found in self.path
+        self.app.is_some() || detect_app(&self.original_dir.join(&self.path), &self.framework).is_some()
+    }
+
+    // The `module:callable` served by gunicorn
+    pub fn app_string(&self) -> Result<String, GsmError> {
+        match &self.app {
+            Some(app) => Ok(app.clone()),
+            None => detect_app(&self.original_dir.join(&self.path), &self.framework)
+                .ok_or_else(|| self.invalid_dir()),
+        }
+    }
+
+    fn invalid_dir(&self) -> GsmError {
+        GsmError::InvalidServerDir(self.original_dir.join(&self.path))
+    }
+
+    // Environment variables injected into the server process, values from `env`
+    // take precedence over the ones loaded from `env_file`
+    pub fn environment(&self) -> Result<BTreeMap<String, String>, GsmError> {
+        let mut vars = BTreeMap::new();
+        if let Some(env_file) = &self.env_file {
+            let env_file = if env_file.is_relative() {
+                self.original_dir.join(env_file)
+            } else {
+                env_file.clone()
+            };
+            vars.extend(parse_env_file(&env_file).map_err(GsmError::InvalidArgument)?);
+        }
+        vars.extend(self.env.clone());
+        Ok(vars)
+    }
+
+    // Environment for maintenance commands (dependency installs, builds) run on behalf
+    // of the server, with its virtualenv activated
+    pub fn command_environment(&self) -> Result<BTreeMap<String, String>, GsmError> {
+        let mut vars = self.environment()?;
+        if let Some(venv) = &self.active_venv() {
+            let path = env::var("PATH").unwrap_or_default();
+            vars.insert(String::from("VIRTUAL_ENV"), venv.display().to_string());
+            vars.insert(String::from("PATH"), format!("{}:{}", venv.join("bin").display(), path));
+        }
+        Ok(vars)
+    }
+
+    // Virtualenv the server runs in, the live release's own one when it has one
+    pub fn active_venv(&self) -> Option<PathBuf> {
+        let release_venv = self.original_dir.join(&self.path).join(RELEASE_VENV);
+        if self.release_root.is_some() && release_venv.exists() {
+            Some(release_venv)
         } else {
-            return false;
+            self.venv.clone()
+        }
+    }
+
+    // Executable of a tool installed in the server's virtualenv, falls back to the PATH
+    pub fn venv_bin(&self, tool: &str) -> String {
+        match &self.active_venv() {
+            Some(venv) => venv.join("bin").join(tool).display().to_string(),
+            None => String::from(tool),
         }
     }
 
-    pub fn start(&mut self) -> Result<(), Box<dyn Error>> {
+    pub fn start(&mut self) -> Result<(), GsmError> {
         if self.is_valid() || self.name.to_lowercase().contains("redis-server") {
+            self.hook(HookKind::PreStart)?;
             if !self.name.to_lowercase().contains("redis-server") {
-                start_gunicorn(self)
+                start_gunicorn(self)?;
             } else {
-                start_redis(self)
+                start_redis(self)?;
             }
+            self.hook(HookKind::PostStart)?;
+            Ok(())
         } else {
-            Err("Not a valid server directory.".into())
+            Err(self.invalid_dir())
         }
     }    
 
-    pub fn stop(&mut self) -> Result<(), Box<dyn Error>> {
+    pub fn stop(&mut self) -> Result<(), GsmError> {
         if self.running {
+            self.hook(HookKind::PreStop)?;
             if !self.name.to_lowercase().contains("redis-server") {
-                stop_gunicorn(self)
+                stop_gunicorn(self)?;
             } else {
-                stop_redis(self)
+                stop_redis(self)?;
             }
+            self.running_address = None;
+            self.hook(HookKind::PostStop)?;
+            Ok(())
         } else {
-            println!("Server [{}] not currently running, doing nothing...", self.name);
+            eprintln!("Server [{}] not currently running, doing nothing...", self.name);
             Ok(())
         }
     }     
 
-    pub fn restart(&mut self) -> Result<(), Box<dyn Error>> {
+    // Runs a lifecycle hook, failures of `post_*` hooks are reported without failing the operation
+    fn hook(&self, kind: HookKind) -> Result<(), GsmError> {
+        match run_hook(self, kind) {
+            Err(e) if !kind.is_pre() => {
+                eprintln!("{}", e);
+                Ok(())
+            },
+            result => result.map_err(GsmError::Hook),
+        }
+    }
+
+    // Takes over the address `current` is serving when `self` replaces its settings while it
+    // runs, the process keeps it until it is restarted
+    pub fn keep_running_address(&mut self, current: &Server) {
+        let address = current.running_address.clone().unwrap_or_else(|| (current.bind.clone(), current.port));
+        self.running_address = if current.running && address != (self.bind.clone(), self.port) {
+            Some(address)
+        } else {
+            None
+        };
+    }
+
+    pub fn restart(&mut self) -> Result<(), GsmError> {
         self.stop()?;
         self.start()?;
         Ok(())
     }
 
-    pub fn monitor(&self) -> Result<(), Box<dyn Error>>  {
-        if self.is_valid() || self.name.to_lowercase().contains("redis-server") {
-            env::set_current_dir(&self.original_dir)?;
+    // Reloads a running server in place, Redis has no graceful reload so it is restarted
+    pub fn reload(&mut self) -> Result<(), GsmError> {
+        if !self.running {
+            eprintln!("Server [{}] not currently running, doing nothing...", self.name);
+            Ok(())
+        } else if !self.name.to_lowercase().contains("redis-server") {
+            reload_gunicorn(self)
+        } else {
+            self.restart()
+        }
+    }
+
+    // Waits until a started server accepts connections, so the servers depending on it can start.
+    // Redis doesn't speak HTTP so only its port is checked.
+    pub fn wait_until_ready(&self) -> Result<(), GsmError> {
+        let timeout = Duration::from_secs(self.timeout.max(30) as u64);
+        if self.name.to_lowercase().contains("redis-server") {
+            wait_until_listening(&self.bind, self.port, timeout)
+        } else {
+            wait_until_healthy(&self.bind, self.serving_port(), "/", timeout)
+        }
+        .map_err(GsmError::HealthCheck)
+    }
+
+    // Pid file written by gunicorn in the state directory. Servers started by older
+    // versions wrote it next to the releases, it's used until they're stopped.
+    pub fn pid_file(&self) -> PathBuf {
+        let file = match &self.blue_green {
+            Some(blue_green) => format!("{}-{}.pid", self.name, blue_green.active),
+            None => format!("{}.pid", self.name),
+        };
+        let pid_file = state::pids_dir().join(&file);
+        let legacy = self.original_dir.join(self.release_root.as_ref().unwrap_or(&self.path)).join(&file);
+        if !pid_file.exists() && legacy.exists() {
+            legacy
+        } else {
+            pid_file
+        }
+    }
+
+    // Port the server currently listens on, the active color's one for blue/green servers
+    pub fn serving_port(&self) -> u32 {
+        match &self.blue_green {
+            Some(blue_green) => blue_green.port(self, blue_green.active),
+            None => self.port,
+        }
+    }
 
+    // Every port reserved by the server
+    pub fn ports(&self) -> Vec<u32> {
+        match &self.blue_green {
+            Some(blue_green) => vec![self.port, blue_green.green_port],
+            None => vec![self.port],
+        }
+    }
+
+    pub fn build_log(&self) -> PathBuf {
+        self.original_dir.join(&self.log_path).join(format!("{}-build.log", self.name))
+    }
+
+    pub fn build_pipeline(&self) -> Vec<BuildStep> {
+        if self.build_steps.is_empty() {
+            default_build_steps(&self.original_dir.join(&self.path))
+        } else {
+            self.build_steps.clone()
+        }
+    }
+
+    // Runs the build steps triggered by the changed files, or all of them if `None`
+    pub fn build(&self, changed_files: Option<&[&str]>) -> Result<usize, GsmError> {
+        run_build_steps(self, &self.build_pipeline(), changed_files, &self.build_log())
+            .map_err(|e| GsmError::Build(format!("Build failed: {}, see {} for details.", e, self.build_log().display())))
+    }
+
+    // Contents of the server's log file
+    pub fn monitor(&self) -> Result<String, GsmError>  {
+        if self.is_valid() || self.name.to_lowercase().contains("redis-server") {
             let monitor_command = format!("cat {}/{}.log", self.log_path.display(), self.name);
             let output = Command::new("sh")
                 .arg("-c")
                 .arg(&monitor_command)
+                .current_dir(&self.original_dir)
                 .output()?;
             
-            if output.status.success() {
-                println!("Successfully retrieved server logs.");
-                let stdout = String::from_utf8_lossy(&output.stdout);
-                   println!("{}", stdout);
-            } else {
-                let error_message = String::from_utf8_lossy(&output.stderr);
-                return Err(format!("Failed retrieve server logs: {}", error_message).into());
+            if !output.status.success() {
+                return Err(GsmError::SpawnFailed {
+                    action: String::from("retrieve server logs"),
+                    stderr: String::from_utf8_lossy(&output.stderr).into_owned(),
+                });
             }
 
-            Ok(())
+            Ok(String::from_utf8_lossy(&output.stdout).into_owned())
 
         } else {
-            Err("Not a valid server directory.".into())
+            Err(self.invalid_dir())
         }
     }
     
-    pub fn clear_logs(&mut self) -> Result<(), Box<dyn Error>> {
+    pub fn clear_logs(&mut self) -> Result<(), GsmError> {
         if self.is_valid() || self.name.to_lowercase().contains("redis-server") {
             let clear_command = format!("> {}/{}.log", self.log_path.display(), self.name);
             let output = Command::new("sh")
                 .arg("-c")
                 .arg(&clear_command)
+                .current_dir(&self.original_dir)
                 .output()?;
 
             if output.status.success() {
-                println!("Successfully cleared server logs.");
+                eprintln!("Successfully cleared server logs.");
             } else {
-                let error_message = String::from_utf8_lossy(&output.stderr);
-                return Err(format!("Failed clear server logs: {}", error_message).into());
+                return Err(GsmError::SpawnFailed {
+                    action: String::from("clear server logs"),
+                    stderr: String::from_utf8_lossy(&output.stderr).into_owned(),
+                });
             }
             
             Ok(())
 
         } else {
-            Err("Not a valid server directory.".into())
+            Err(self.invalid_dir())
         }
     }
 
     ///////////////////////////WORK ON NEXT//////////////////////////////
-    pub fn git_init(&mut self) {
+    pub fn git_init(&mut self) -> Result<(), GsmError> {
+        if self.github {
+            return Err(GsmError::InvalidState(String::from("Directory already connected to git.")));
+        }
+        initialize_git_repository(&self.original_dir.join(&self.path))
+            .map_err(|e| GsmError::Git(format!("Failed to initialize git repository: {}", e)))?;
+        self.github = true;
+        eprintln!("Initialized git repository for [{}]", self.name);
+        Ok(())
+    }
+
+    pub fn git_set_origin(&mut self, remote_url: &str) -> Result<(), GsmError> {
         if !self.github {
-            initialize_git_repository(&self.path);
-        } else {
-            println!("Directory already connect to git.")
+            return Err(GsmError::InvalidState(String::from("Not a valid git repository, run `git_init` first.")));
         }
+        add_remote_origin(&self.work_tree()?, remote_url)
+            .map_err(|e| GsmError::Git(format!("Failed to add remote origin: {}", e)))?;
+        eprintln!("Set origin of [{}] to {}", self.name, remote_url);
+        Ok(())
     }
 
-    pub fn git_set_origin(&mut self, remote_url: &str) {
-        add_remote_origin(&self.path, remote_url);
-    }    
+    // Fetches from origin and reads the state of the live checkout. For a releases
+    // layout, the incoming files are the ones changed since the deployed release.
+    pub fn git_status(&self) -> Result<GitStatus, GsmError> {
+        if !self.github {
+            return Err(GsmError::InvalidState(String::from("Not a valid git repository.")));
+        }
 
-    pub fn update(&mut self) {
-        // Update the server
-        if self.github && self.is_valid() {
-            // Pull the latest changes from the Git repository
-            let pull_result;
-            {
-                env::set_current_dir(&self.original_dir).unwrap();
-                pull_result = git_pull(&self.path);
-                env::set_current_dir(&self.original_dir).unwrap();
+        let path = self.original_dir.join(&self.path);
+        if self.release_root.is_none() {
+            if let Err(e) = git_fetch(&path) {
+                eprintln!("Failed to fetch from origin, showing the last fetched state: {}", e);
             }
-            
-            if let Err(e) = pull_result {
-                println!("Failed to pull the latest changes from the Git repository: {}", e);
-                return;
+            return git_status(&path).map_err(GsmError::Git);
+        }
+
+        let repository = self.repository();
+        if let Err(e) = git_remote_update(&repository) {
+            eprintln!("Failed to fetch from origin, showing the last fetched state: {}", e);
+        }
+        let mut status = git_status(&path).map_err(GsmError::Git)?;
+        if let Some(latest) = git_rev_parse("HEAD", &repository).map_err(GsmError::Git)? {
+            if latest != status.head {
+                status.upstream = git_current_branch(&repository).map_err(GsmError::Git)?;
+                status.incoming = git_diff_name_only(&status.head, &latest, &repository).map_err(GsmError::Git)?
+                    .lines()
+                    .map(|line| line.to_string())
+                    .collect();
+                status.behind = git_commit_count(&status.head, &latest, &repository).unwrap_or(0);
             }
-    
-            let diff_output = match git_diff_name_only("HEAD", "HEAD~1", &self.path) {
-                Ok(output) => output,
-                Err(e) => {
-                    println!("Failed to get the diff: {}", e);
-                    return;
-                }
-            };
-    
-            if contains_compiled_files(&diff_output) {
-                println!("C++ source files or CMakeLists.txt have changed, rebuilding...");
-    
-                if diff_output.contains("CMakeLists.txt") {
-                    println!("CMakeLists.txt has changed, re-running cmake...");
-                    {
-                        env::set_current_dir(&self.original_dir).unwrap();
-                        let result = run_cmake(&self.path);
-                        env::set_current_dir(&self.original_dir).unwrap();
-                        if let Err(e) = result {
-                            println!("Failed to run cmake: {}", e);
-                            return;
-                        }
-                    }
-                }
-    
-                {
-                    env::set_current_dir(&self.original_dir).unwrap();
-                    let result = compile_and_install_project(&self.path);
-                    env::set_current_dir(&self.original_dir).unwrap();
-                    if let Err(e) = result {
-                        println!("Failed to compile and install the project: {}", e);
-                        return;
-                    }
-                }
-    
-                println!("Update completed successfully.");
-            } else {
-                println!("No C++ source files or CMakeLists.txt changes found.");
+        }
+        Ok(status)
+    }
+
+    // Short hash of the commit being served, for display
+    pub fn short_commit(&self) -> Option<String> {
+        let commit = match &self.deployed_commit {
+            Some(commit) => Some(commit.clone()),
+            None if self.github => git_rev_parse("HEAD", &self.original_dir.join(&self.path)).ok().flatten(),
+            None => None,
+        };
+        commit.map(|commit| short_sha(&commit).to_string())
+    }
+
+    // Repository holding the deployable refs, the mirror cache for a releases layout
+    fn repository(&self) -> PathBuf {
+        match &self.release_root {
+            Some(root) => root.join("repo"),
+            None => self.original_dir.join(&self.path),
+        }
+    }
+
+    // Branch whose new commits are deployed by `update`
+    pub fn tracked_branch(&self) -> Result<Option<String>, String> {
+        match &self.branch {
+            Some(branch) => Ok(Some(branch.clone())),
+            None => git_current_branch(&self.repository()),
+        }
+    }
+
+    // Working tree of the server. A directory nested in another repository is refused,
+    // checkouts there would move the parent repository.
+    fn work_tree(&self) -> Result<PathBuf, GsmError> {
+        let path = self.original_dir.join(&self.path);
+        if !self.github || !self.is_valid() {
+            return Err(GsmError::InvalidState(String::from("Not a valid git repository.")));
+        }
+        if !is_repository_root(&path) {
+            return Err(GsmError::InvalidState(format!("{} is inside another git repository, not the root of its own.", path.display())));
+        }
+        Ok(path)
+    }
+
+    // Commit the tracked branch points to on origin
+    fn upstream_commit(&self, repository: &Path) -> Result<(String, String), String> {
+        let branch = self.tracked_branch()?
+            .ok_or("No branch to update from, deploy one with `deploy --ref {branch}`.")?;
+        let upstream = git_rev_parse(&format!("origin/{}", branch), repository)?
+            .ok_or_else(|| format!("Branch {} not found on origin.", branch))?;
+        Ok((branch, upstream))
+    }
+
+    pub fn remote_url(&self) -> Result<Option<String>, String> {
+        git_remote_url(&self.repository())
+    }
+
+    // Fetches from origin and checks whether `update` would bring in new commits
+    pub fn has_pending_commits(&self) -> Result<bool, GsmError> {
+        self.pending_commits().map_err(GsmError::Git)
+    }
+
+    fn pending_commits(&self) -> Result<bool, String> {
+        let repository = self.repository();
+
+        if self.release_root.is_some() {
+            git_remote_update(&repository)?;
+            let head = git_rev_parse("HEAD", &repository)?;
+            return Ok(head.is_some() && head != self.deployed_commit);
+        }
+
+        git_fetch(&repository)?;
+        let head = git_rev_parse("HEAD", &repository)?.ok_or("Repository has no commits yet.")?;
+        let (_, upstream) = self.upstream_commit(&repository)?;
+        Ok(head != upstream && git_is_ancestor(&head, &upstream, &repository)?)
+    }
+
+    pub fn update(&mut self) -> Result<(), GsmError> {
+        self.hook(HookKind::PreUpdate)?;
+        self.pull_changes()?;
+        self.hook(HookKind::PostUpdate)?;
+        Ok(())
+    }
+
+    // Fast-forwards the tracked branch to origin and applies the changes, with a releases
+    // layout the default branch of the repository is deployed as a new release instead
+    fn pull_changes(&mut self) -> Result<(), GsmError> {
+        if self.release_root.is_some() {
+            return deploy_release(self, "HEAD");
+        }
+        let path = self.work_tree()?;
+
+        let head = git_rev_parse("HEAD", &path)
+            .map_err(|e| format!("Failed to read the current commit: {}", e))
+            .and_then(|head| head.ok_or_else(|| String::from("Repository has no commits yet.")))
+            .map_err(GsmError::Git)?;
+
+        git_fetch(&path)
+            .map_err(|e| GsmError::Git(format!("Failed to fetch the latest changes from the Git repository: {}", e)))?;
+        let (branch, upstream) = self.upstream_commit(&path).map_err(GsmError::Git)?;
+        if git_current_branch(&path).map_err(GsmError::Git)?.as_deref() == Some(branch.as_str()) {
+            if !git_is_ancestor(&head, &upstream, &path).map_err(GsmError::Git)? {
+                return Err(GsmError::Git(format!("[{}] has local commits that are not on origin/{}, merge or drop them before updating.", self.name, branch)));
             }
+            git_fast_forward(&branch, &path).map_err(GsmError::Git)?;
         } else {
-            println!("Not a valid git repository.");
+            // Back on the branch from the detached HEAD left by deploying a tag or commit
+            git_checkout(&branch, &path).map_err(GsmError::Git)?;
+        }
+        self.branch = Some(branch);
+
+        let new_head = git_rev_parse("HEAD", &path)
+            .ok()
+            .flatten()
+            .ok_or_else(|| GsmError::Git(String::from("Failed to read the pulled commit.")))?;
+
+        self.apply_changes(&head, &new_head)?;
+        self.record_deploy(&head, &new_head);
+        Ok(())
+    }
+
+    // Checks out `rev` (branch, tag or commit), then syncs dependencies, builds and reloads.
+    // The previous commit is checked out again if any of those steps fail.
+    pub fn deploy(&mut self, rev: &str) -> Result<(), GsmError> {
+        self.hook(HookKind::PreUpdate)?;
+        self.checkout_and_apply(rev)?;
+        self.hook(HookKind::PostUpdate)?;
+        Ok(())
+    }
+
+    fn checkout_and_apply(&mut self, rev: &str) -> Result<(), GsmError> {
+        if self.release_root.is_some() {
+            return deploy_release(self, rev);
+        }
+
+        let path = self.work_tree()?;
+        let head = git_rev_parse("HEAD", &path)
+            .map_err(GsmError::Git)?
+            .ok_or_else(|| GsmError::Git(String::from("Repository has no commits yet.")))?;
+        if let Err(e) = git_fetch(&path) {
+            eprintln!("Failed to fetch from origin, deploying from local refs: {}", e);
         }
-    }            
-}
\ No newline at end of file
+        git_rev_parse(rev, &path)
+            .map_err(GsmError::Git)?
+            .ok_or_else(|| GsmError::InvalidArgument(format!("Unknown ref '{}'", rev)))?;
+
+        // Remember the branch before a tag or commit detaches HEAD, so `update` can follow it
+        if self.branch.is_none() {
+            self.branch = git_current_branch(&path).map_err(GsmError::Git)?;
+        }
+        let is_branch = git_rev_parse(&format!("origin/{}", rev), &path).map_err(GsmError::Git)?.is_some();
+
+        git_checkout(rev, &path).map_err(GsmError::Git)?;
+        let new_head = git_rev_parse("HEAD", &path)
+            .map_err(GsmError::Git)?
+            .ok_or_else(|| GsmError::Git(String::from("Failed to read the deployed commit.")))?;
+
+        if let Err(e) = self.apply_changes(&head, &new_head) {
+            eprintln!("Deploy failed, checking out {} again...", short_sha(&head));
+            git_checkout(&head, &path).map_err(GsmError::Git)?;
+            return Err(e);
+        }
+
+        if is_branch {
+            self.branch = Some(rev.to_string());
+        }
+        self.record_deploy(&head, &new_head);
+        eprintln!("Deployed {} ({}) to [{}]", rev, short_sha(&new_head), self.name);
+        Ok(())
+    }
+
+    // Deploys the commit that was live before the last deploy, or the previous release,
+    // with the same update hooks as `deploy`
+    pub fn rollback(&mut self) -> Result<(), GsmError> {
+        let previous = match self.release_root {
+            Some(_) => None,
+            None => Some(self.previous_commit.clone()
+                .ok_or_else(|| GsmError::InvalidState(String::from("No previous deploy to roll back to.")))?),
+        };
+
+        self.hook(HookKind::PreUpdate)?;
+        match previous {
+            Some(previous) => self.checkout_and_apply(&previous)?,
+            None => rollback_release(self)?,
+        }
+        self.hook(HookKind::PostUpdate)?;
+        Ok(())
+    }
+
+    // Syncs dependencies, runs the build pipeline for the files changed between
+    // two commits and the Django deploy steps, then reloads the server if it's running
+    fn apply_changes(&mut self, from: &str, to: &str) -> Result<(), GsmError> {
+        if from == to {
+            eprintln!("Already up to date.");
+            return Ok(());
+        }
+
+        let diff_output = git_diff_name_only(from, to, &self.original_dir.join(&self.path))
+            .map_err(|e| GsmError::Git(format!("Failed to get the diff: {}", e)))?;
+
+        self.sync_changed_dependencies(&changed_dependency_files(&diff_output))?;
+
+        let changed_files = diff_output.lines().collect::<Vec<_>>();
+        match self.build(Some(&changed_files)) {
+            Ok(0) => eprintln!("No build steps triggered by the changes."),
+            Ok(steps) => eprintln!("Build completed successfully ({} steps).", steps),
+            Err(e) => return Err(e),
+        }
+        run_deploy_steps(self)?;
+
+        if self.running {
+            self.activate()?;
+        }
+        Ok(())
+    }
+
+    // Puts freshly deployed code into service, with a blue/green switch when enabled
+    pub(crate) fn activate(&mut self) -> Result<(), GsmError> {
+        if self.blue_green.is_some() && self.running {
+            switch_colors(self)
+        } else {
+            self.reload()
+        }
+    }
+
+    // Installs the dependencies declared in the given dependency files into the virtualenv
+    pub(crate) fn sync_changed_dependencies(&self, changed_dependencies: &[&str]) -> Result<(), GsmError> {
+        if changed_dependencies.is_empty() {
+            return Ok(());
+        }
+
+        eprintln!("Python dependencies have changed ({}), syncing...", changed_dependencies.join(", "));
+        let path = self.original_dir.join(&self.path);
+        let pip = self.active_venv().map(|venv| venv.join("bin").join("pip").display().to_string());
+        let commands = install_commands(changed_dependencies, &path, pip.as_deref());
+        if pip.is_none() && install_commands(changed_dependencies, &path, Some("pip")).len() > commands.len() {
+            eprintln!("[WARNING] [{}] has no virtualenv, skipping `pip install` instead of changing the system Python, set one with `edit --venv`", self.name);
+        }
+        sync_dependencies(self, &commands)
+            .map_err(|e| GsmError::Build(format!("Failed to sync dependencies: {}", e)))
+    }
+
+    pub(crate) fn record_deploy(&mut self, from: &str, to: &str) {
+        if from != to && !from.is_empty() {
+            self.previous_commit = Some(from.to_string());
+        }
+        self.deployed_commit = Some(to.to_string());
+    }
+}
+
+pub fn short_sha(sha: &str) -> &str {
+    &sha[..sha.len().min(7)]
+}
@@ -1,293 +1,1006 @@
 use crate::server::server::Server;
+use crate::error::GsmError;
+use crate::utils::dotenv::mask_value;
+use crate::utils::build::BuildStep;
+use crate::server::django;
+use crate::server::snapshots::{self, Snapshot, SnapshotInfo};
+use crate::server::bulk::{OperationResult, Status, run_parallel};
+use crate::server::reports::{ServerInfo, LogsReport, EnvReport, HooksReport, BuildStepsReport, ReleasesReport, GitStatusReport};
+use crate::utils::deps::DEPENDENCY_FILES;
+use crate::utils::state::{self, StateLock};
+use crate::utils::hooks::{Hook, HookKind, DEFAULT_HOOK_TIMEOUT};
+use crate::server::releases::{enable_releases, list_releases};
+use crate::server::blue_green::{BlueGreen, Color, switch_colors};
+use crate::github::poller::{AutoUpdate, MaintenanceWindow, utc_minute};
+use std::collections::BTreeMap;
+use glob::Pattern;
 use std::path::Path;
 use std::path::PathBuf;
-use std::error::Error;
+use std::fs;
 use std::fs::File;
 use std::io::prelude::*;
 use serde_json;
 use serde::{Serialize, Deserialize};
 use std::process::Command;
+use std::sync::Mutex;
 
 
-#[derive(Debug)]
+// Log visualization scripts, built into the binary so `visualize` doesn't depend on
+// where the sources are. They are written to `scripts/` of the state directory to run.
+const SCRIPTS: [(&str, &str); 3] = [
+    ("main.py", include_str!("../../scripts/main.py")),
+    ("data_processing.py", include_str!("../../scripts/data_processing.py")),
+    ("visualizer.py", include_str!("../../scripts/visualizer.py")),
+];
+
+// Settings changed by the `edit` command, `None` leaves the current value in place and
+// `Some(None)` clears an optional one
+#[derive(Debug, Default)]
+pub struct ServerEdit {
+    pub port: Option<u32>,
+    pub workers: Option<u32>,
+    pub timeout: Option<u32>,
+    pub bind: Option<String>,
+    pub log_path: Option<PathBuf>,
+    pub env_file: Option<Option<PathBuf>>,
+    pub venv: Option<Option<PathBuf>>,
+    pub app: Option<Option<String>>,
+    pub tags: Vec<String>,
+    pub untag: Vec<String>,
+    pub depends_on: Vec<String>,
+    pub drop_depends_on: Vec<String>,
+    pub restart: bool,
+}
+
+#[derive(Debug, Default)]
 pub struct Servers {
     pub servers: Vec<Server>,
+    claims: Mutex<Vec<StateLock>>, // State files this instance writes, no other instance can until it is dropped
 }
 
 impl Servers {
+    pub fn new(servers: Vec<Server>) -> Servers {
+        Servers {
+            servers,
+            claims: Mutex::new(Vec::new()),
+        }
+    }
+
     pub fn num_servers(&self) -> usize {
         self.servers.len()
     }
 
-    pub fn list_all(&mut self) {
-        println!("[INFO] Listing all available servers");
-        println!("[INFO] [*]: Running | [ ]: Not running \n");
-        for server in &mut self.servers {
-            let symbol = if server.running {
-                String::from("*")
-            } else {
-                String::from(" ")
-            };
-            println!("[{}] Name: {} | Address: {}:{} | Workers: {} | Timeout: {}s | Log Path: {} |", 
-                symbol,
-                server.name, 
-                server.bind, 
-                server.port, 
-                server.workers,
-                server.timeout,
-                server.log_path.display()
-            );
+    pub fn list_all(&self) -> Vec<ServerInfo> {
+        self.servers.iter()
+            .map(|server| ServerInfo {
+                name: server.name.clone(),
+                running: server.running,
+                bind: server.bind.clone(),
+                port: server.serving_port(),
+                color: server.blue_green.as_ref().map(|blue_green| blue_green.active),
+                workers: server.workers,
+                timeout: server.timeout,
+                log_path: server.log_path.clone(),
+                commit: server.short_commit(),
+                env: server.env.iter().map(|(key, value)| (key.clone(), mask_value(value))).collect(),
+                tags: server.tags.clone(),
+                depends_on: server.depends_on.clone(),
+                restart_required: server.running_address.is_some(),
+            })
+            .collect()
+    }
+
+    pub fn add_server(&mut self, new_server: Server) -> Result<(), GsmError> {
+        self.check_unique(&new_server, None)?;
+        self.check_dependencies(&new_server)?;
+
+        let new_server_name = new_server.name.clone();
+        self.servers.push(new_server);
+        self.backup()?;
+        eprintln!("Successfully added [{}]", new_server_name);
+        Ok(())
+    }
+
+    pub fn edit_server(&mut self, name: &str, edit: ServerEdit) -> Result<(), GsmError> {
+        let index = self.position(name)?;
+
+        let mut edited = self.servers[index].clone();
+        if let Some(port) = edit.port {
+            edited.port = port;
+        }
+        if let Some(workers) = edit.workers {
+            edited.workers = workers;
         }
+        if let Some(timeout) = edit.timeout {
+            edited.timeout = timeout;
+        }
+        if let Some(bind) = edit.bind {
+            edited.bind = bind;
+        }
+        if let Some(log_path) = edit.log_path {
+            edited.log_path = log_path;
+        }
+        if let Some(env_file) = edit.env_file {
+            edited.env_file = env_file;
+        }
+        if let Some(venv) = edit.venv {
+            edited.venv = venv;
+        }
+        if let Some(app) = edit.app {
+            edited.app = app;
+        }
+        edited.tags.retain(|tag| !edit.untag.contains(tag));
+        for tag in edit.tags {
+            if !edited.tags.contains(&tag) {
+                edited.tags.push(tag);
+            }
+        }
+        edited.depends_on.retain(|dependency| !edit.drop_depends_on.contains(dependency));
+        for dependency in edit.depends_on {
+            if !edited.depends_on.contains(&dependency) {
+                edited.depends_on.push(dependency);
+            }
+        }
+
+        // Re-run the same checks as `add_server`, ignoring the server being edited
+        self.check_unique(&edited, Some(name))?;
+        self.check_dependencies(&edited)?;
+
+        edited.keep_running_address(&self.servers[index]);
+        self.servers[index] = edited;
+        self.backup()?;
+        eprintln!("Successfully edited [{}]", name);
+
+        if edit.restart && self.servers[index].running {
+            self.servers[index].restart()?;
+            self.backup()?;
+        } else if self.servers[index].running_address.is_some() {
+            eprintln!("[INFO] [{}] still serves its previous address, restart it to apply the new one", name);
+        }
+        Ok(())
     }
 
-    pub fn add_server(&mut self, new_server: Server) -> Result<(), String> {
-        if self.name_exists(&new_server.name) {
-            return Err(String::from("Server name already exists"));
+    pub fn set_env(&mut self, name: &str, vars: Vec<(String, String)>) -> Result<(), GsmError> {
+        let index = self.position(name)?;
+
+        for (key, value) in vars {
+            eprintln!("Set {} for [{}]", key, name);
+            self.servers[index].env.insert(key, value);
         }
+        self.backup()?;
+        Ok(())
+    }
 
-        if self.path_exists(&new_server.path) {
-            return Err(String::from("Server path already exists"));
+    pub fn unset_env(&mut self, name: &str, keys: &[String]) -> Result<(), GsmError> {
+        let index = self.position(name)?;
+
+        for key in keys {
+            if self.servers[index].env.remove(key).is_some() {
+                eprintln!("Unset {} for [{}]", key, name);
+            } else {
+                eprintln!("{} is not set for [{}]", key, name);
+            }
         }
+        self.backup()?;
+        Ok(())
+    }
+
+    pub fn list_env(&self, name: &str, show: bool) -> Result<EnvReport, GsmError> {
+        let server = self.find(name)?;
+
+        let vars = server.environment()?
+            .into_iter()
+            .map(|(key, value)| {
+                let value = if show { value } else { mask_value(&value) };
+                (key, value)
+            })
+            .collect();
+        Ok(EnvReport {
+            name: name.to_string(),
+            env_file: server.env_file.clone(),
+            vars,
+        })
+    }
+
+    pub fn set_hook(&mut self, name: &str, kind: HookKind, command: String, timeout: Option<u64>) -> Result<(), GsmError> {
+        let index = self.position(name)?;
+
+        let hook = Hook {
+            command,
+            timeout: timeout.unwrap_or(DEFAULT_HOOK_TIMEOUT),
+        };
+        eprintln!("Set {} hook of [{}] to `{}`", kind, name, hook.command);
+        self.servers[index].hooks.insert(kind, hook);
+        self.backup()?;
+        Ok(())
+    }
+
+    pub fn unset_hook(&mut self, name: &str, kind: HookKind) -> Result<(), GsmError> {
+        let index = self.position(name)?;
 
-        if self.port_exists(new_server.port) {
-            return Err(String::from("Server port already exists"));
+        if self.servers[index].hooks.remove(&kind).is_none() {
+            return Err(GsmError::InvalidArgument(format!("No {} hook set for [{}]", kind, name)));
         }
+        eprintln!("Removed {} hook from [{}]", kind, name);
+        self.backup()?;
+        Ok(())
+    }
 
-        let new_server_name = new_server.name.clone();
-        self.servers.push(new_server);
-        self.backup();
-        println!("Successfully added [{}]", new_server_name);
+    pub fn list_hooks(&self, name: &str) -> Result<HooksReport, GsmError> {
+        let server = self.find(name)?;
+
+        Ok(HooksReport {
+            name: name.to_string(),
+            hooks: server.hooks.clone(),
+        })
+    }
+
+    pub fn add_build_step(&mut self, name: &str, step: BuildStep) -> Result<(), GsmError> {
+        let index = self.position(name)?;
+
+        // Validate the patterns up front rather than on the next update
+        step.is_triggered(&[]).map_err(GsmError::InvalidArgument)?;
+        eprintln!("Added build step `{}` to [{}]", step.command, name);
+        self.servers[index].build_steps.push(step);
+        self.backup()?;
         Ok(())
     }
 
+    pub fn remove_build_step(&mut self, name: &str, step: usize) -> Result<(), GsmError> {
+        let index = self.position(name)?;
+
+        if step >= self.servers[index].build_steps.len() {
+            return Err(GsmError::InvalidArgument(format!("Build step {} not found", step)));
+        }
+        let removed = self.servers[index].build_steps.remove(step);
+        eprintln!("Removed build step `{}` from [{}]", removed.command, name);
+        self.backup()?;
+        Ok(())
+    }
+
+    pub fn list_build_steps(&self, name: &str) -> Result<BuildStepsReport, GsmError> {
+        let server = self.find(name)?;
+
+        Ok(BuildStepsReport {
+            name: name.to_string(),
+            default: server.build_steps.is_empty(),
+            steps: server.build_pipeline(),
+        })
+    }
+
+    pub fn build(&mut self, name: &str) -> Result<(), GsmError> {
+        let server = self.find(name)?;
+
+        let steps = server.build(None)?;
+        eprintln!("Build completed successfully ({} steps).", steps);
+        Ok(())
+    }
+
+    pub fn manage(&self, name: &str, args: &[String]) -> Result<(), GsmError> {
+        let server = self.find(name)?;
+        django::manage(server, args)
+    }
+
+    // Installs every declared dependency and runs the whole build pipeline,
+    // for servers that were just cloned
+    pub fn install(&mut self, name: &str) -> Result<(), GsmError> {
+        let server = self.find(name)?;
+        let path = server.original_dir.join(&server.path);
+
+        let dependencies = DEPENDENCY_FILES.iter()
+            .copied()
+            .filter(|file| path.join(file).exists())
+            .collect::<Vec<_>>();
+        server.sync_changed_dependencies(&dependencies)?;
+        self.build(name)
+    }
+
     pub fn fetch_server(&self, name: &str) -> Option<&Server> {
         self.servers.iter().find(|s| s.name == name)
     }
 
-    pub fn remove_server(&mut self, name: &str) -> Result<(), String> {
-        let index = self.servers.iter().position(|s| s.name == name);
+    // Like `fetch_server`, with a missing server as an error
+    fn find(&self, name: &str) -> Result<&Server, GsmError> {
+        self.fetch_server(name).ok_or_else(|| GsmError::NotFound(name.to_string()))
+    }
+
+    fn position(&self, name: &str) -> Result<usize, GsmError> {
+        self.servers.iter().position(|s| s.name == name).ok_or_else(|| GsmError::NotFound(name.to_string()))
+    }
 
-        if let Some(index) = index {
-            // Safely shut down the server before removing
-            self.servers[index].stop();
+    pub fn remove_server(&mut self, name: &str) -> Result<(), GsmError> {
+        let index = self.position(name)?;
 
-            self.servers.remove(index);
-            println!("Successfully removed [{}]", name);
-            self.backup();
-            Ok(())
-        } else {
-            Err(String::from("Server not found"))
+        if let Some(dependent) = self.servers.iter().find(|s| s.depends_on.iter().any(|d| d == name)) {
+            return Err(GsmError::Dependency(format!("[{}] depends on [{}], remove the dependency first", dependent.name, name)));
         }
-    }
 
-    pub fn start_server(&mut self, name: &str) -> Result<(), Box<dyn Error>> {
-        let index = self.servers.iter().position(|s| s.name == name);
-    
-        if let Some(index) = index {
-            // Safely shut down the server before removing
-            self.servers[index].start()?;
-            self.backup();
-            Ok(())
-        } else {
-            Err("Server not found".into())
+        // Safely shut down the server before removing
+        if let Err(e) = self.servers[index].stop() {
+            eprintln!("[ERROR] {}", e);
         }
+
+        self.servers.remove(index);
+        eprintln!("Successfully removed [{}]", name);
+        self.backup()
+    }
+
+    pub fn start_server(&mut self, name: &str) -> Result<(), GsmError> {
+        let index = self.position(name)?;
+
+        self.servers[index].start()?;
+        eprintln!("Successfully started [{}]", name);
+        self.backup()
     }    
 
-    pub fn stop_server(&mut self, name: &str) -> Result<(), Box<dyn Error>> {
-        let index = self.servers.iter().position(|s| s.name == name);
+    pub fn stop_server(&mut self, name: &str) -> Result<(), GsmError> {
+        let index = self.position(name)?;
 
-        if let Some(index) = index {
-            // Safely shut down the server before removing
-            self.servers[index].stop()?;
-            self.backup();
-            Ok(())
-        } else {
-            Err("Server not found".into())
+        self.servers[index].stop()?;
+        eprintln!("Successfully stopped [{}]", name);
+        self.backup()
+    }
+
+    // Starts the servers in waves, each wave holding the servers whose dependencies were started
+    // by the previous ones. Dependencies that aren't running are started too and must accept
+    // connections before their dependents start, dependents of a failed server are skipped.
+    pub fn start_servers(&mut self, names: &[String], parallelism: usize) -> Result<Vec<OperationResult>, GsmError> {
+        let waves = self.dependency_waves(names)?;
+        let dependencies = self.servers.iter()
+            .filter(|s| waves.iter().flatten().any(|name| *name == s.name))
+            .flat_map(|s| s.depends_on.clone())
+            .collect::<Vec<_>>();
+
+        let mut results: Vec<OperationResult> = Vec::new();
+        for wave in waves {
+            let mut to_start = Vec::new();
+            for name in wave {
+                let server = self.find(&name)?;
+                let failed = server.depends_on.iter().find(|dependency| {
+                    results.iter().any(|result| result.name == **dependency && result.status != Status::Ok)
+                });
+                if let Some(dependency) = failed {
+                    results.push(OperationResult::skipped(&name, format!("Dependency [{}] failed", dependency)));
+                } else if names.contains(&name) || !server.running {
+                    to_start.push(name);
+                }
+            }
+
+            let servers = self.servers.iter_mut().filter(|s| to_start.contains(&s.name));
+            results.extend(run_parallel(servers, parallelism, |server| -> Result<(), GsmError> {
+                server.start()?;
+                if dependencies.contains(&server.name) {
+                    server.wait_until_ready()?;
+                }
+                Ok(())
+            }));
         }
+        self.backup()?;
+        Ok(results)
     }
 
-    pub fn restart_server(&mut self, name: &str) -> Result<(), Box<dyn Error>> {
-        let index = self.servers.iter().position(|s| s.name == name);
+    // Stops the servers in the reverse order of `start_servers`, dependents before their dependencies.
+    // Running dependents are stopped too, and a server is kept running if one of them fails to stop.
+    pub fn stop_servers(&mut self, names: &[String], parallelism: usize) -> Result<Vec<OperationResult>, GsmError> {
+        let to_stop = self.with_running_dependents(names);
+        for name in to_stop.iter().filter(|name| !names.contains(name)) {
+            eprintln!("Stopping [{}] too, it depends on a server being stopped", name);
+        }
+        let waves = self.dependency_waves(&to_stop)?;
 
-        if let Some(index) = index {
-            self.servers[index].restart()?;
-            self.backup();
-            Ok(())
-        } else {
-            Err("Server not found".into())
+        let mut results: Vec<OperationResult> = Vec::new();
+        for wave in waves.iter().rev() {
+            let mut stopping = Vec::new();
+            for name in wave.iter().filter(|name| to_stop.contains(name)) {
+                let failed = results.iter()
+                    .filter(|result| result.status != Status::Ok)
+                    .find(|result| self.fetch_server(&result.name).is_some_and(|s| s.depends_on.contains(name)));
+                match failed {
+                    Some(result) => results.push(OperationResult::skipped(name, format!("Dependent [{}] failed to stop", result.name))),
+                    None => stopping.push(name.clone()),
+                }
+            }
+            let servers = self.servers.iter_mut().filter(|s| stopping.contains(&s.name));
+            results.extend(run_parallel(servers, parallelism, |server| server.stop()));
         }
+        self.backup()?;
+        Ok(results)
     }
 
-    pub fn flush(&mut self) {
+    // The servers followed by the running servers depending on them, directly or not
+    pub fn with_running_dependents(&self, names: &[String]) -> Vec<String> {
+        let mut servers = names.to_vec();
+        let mut index = 0;
+        while index < servers.len() {
+            let dependents = self.servers.iter()
+                .filter(|s| s.running && s.depends_on.contains(&servers[index]) && !servers.contains(&s.name))
+                .map(|s| s.name.clone())
+                .collect::<Vec<_>>();
+            servers.extend(dependents);
+            index += 1;
+        }
+        servers
+    }
+
+    // Runs an operation on the servers concurrently, regardless of their dependencies
+    pub fn run_bulk<F>(&mut self, names: &[String], parallelism: usize, operation: F) -> Result<Vec<OperationResult>, GsmError>
+    where
+        F: Fn(&mut Server) -> Result<(), GsmError> + Sync,
+    {
+        let servers = self.servers.iter_mut().filter(|s| names.contains(&s.name));
+        let results = run_parallel(servers, parallelism, operation);
+        self.backup()?;
+        Ok(results)
+    }
+
+    // Groups the servers and their dependencies so each group only depends on earlier ones
+    fn dependency_waves(&self, names: &[String]) -> Result<Vec<Vec<String>>, GsmError> {
+        let order = self.dependency_order(names)?;
+
+        let mut waves: Vec<Vec<String>> = Vec::new();
+        for name in order {
+            let server = self.find(&name)?;
+            let wave = server.depends_on.iter()
+                .filter_map(|dependency| waves.iter().position(|wave| wave.contains(dependency)))
+                .map(|wave| wave + 1)
+                .max()
+                .unwrap_or(0);
+            if wave == waves.len() {
+                waves.push(Vec::new());
+            }
+            waves[wave].push(name);
+        }
+        Ok(waves)
+    }
+
+    // Orders the servers and everything they depend on so each one comes after its dependencies
+    pub fn dependency_order(&self, names: &[String]) -> Result<Vec<String>, GsmError> {
+        let mut order = Vec::new();
+        let mut path = Vec::new();
+        for name in names {
+            self.visit_dependencies(name, &mut path, &mut order)?;
+        }
+        Ok(order)
+    }
+
+    // Depth first walk of the dependencies, `path` holds the servers being visited to detect cycles
+    fn visit_dependencies(&self, name: &str, path: &mut Vec<String>, order: &mut Vec<String>) -> Result<(), GsmError> {
+        if order.iter().any(|visited| visited == name) {
+            return Ok(());
+        }
+        if let Some(start) = path.iter().position(|visiting| visiting == name) {
+            let mut cycle = path[start..].to_vec();
+            cycle.push(name.to_string());
+            return Err(GsmError::Dependency(format!("Dependency cycle: {}", cycle.join(" -> "))));
+        }
+
+        let server = self.find(name)?;
+        path.push(name.to_string());
+        for dependency in &server.depends_on {
+            self.visit_dependencies(dependency, path, order)?;
+        }
+        path.pop();
+        order.push(name.to_string());
+        Ok(())
+    }
+
+    // Checks that the dependencies of a new or edited server exist and don't form a cycle
+    fn check_dependencies(&self, server: &Server) -> Result<(), GsmError> {
+        if server.depends_on.contains(&server.name) {
+            return Err(GsmError::Dependency(String::from("A server can't depend on itself")));
+        }
+        if let Some(missing) = server.depends_on.iter().find(|dependency| !self.name_exists(dependency)) {
+            return Err(GsmError::Dependency(format!("Unknown dependency [{}]", missing)));
+        }
+
+        let mut servers = self.servers.iter()
+            .filter(|s| s.name != server.name)
+            .cloned()
+            .collect::<Vec<_>>();
+        servers.push(server.clone());
+        let servers = Servers::new(servers);
+        let mut order = Vec::new();
+        servers.visit_dependencies(&server.name, &mut Vec::new(), &mut order)
+    }
+
+    pub fn restart_server(&mut self, name: &str) -> Result<(), GsmError> {
+        let index = self.position(name)?;
+
+        self.servers[index].restart()?;
+        eprintln!("Successfully restarted [{}]", name);
+        self.backup()
+    }
+
+    // Stops and removes every server, the ones that failed to stop are kept
+    pub fn flush(&mut self) -> Result<(), GsmError> {
         let mut errors = vec![];
         let mut stopped_indices = Vec::new();
-    
+
         for (index, server) in self.servers.iter_mut().enumerate() {
             match server.stop() {
                 Ok(_) => stopped_indices.push(index),
                 Err(e) => errors.push(format!("Failed to stop server {}: {}", server.name, e)),
             }
         }
-    
+
         // Remove the successfully stopped servers in reverse order
         for i in stopped_indices.into_iter().rev() {
             self.servers.remove(i);
         }
-    
-        self.backup();
-    
-        // Print out any errors that occurred
+
+        self.backup()?;
         if !errors.is_empty() {
-            for error in &errors {
-                eprintln!("{}", error);
-            }
+            return Err(GsmError::InvalidState(errors.join("\n")));
         }
+        Ok(())
     }
 
-    pub fn monitor(&mut self, name: &str) -> Result<(), Box<dyn Error>> {
-        let index = self.servers.iter().position(|s| s.name == name);
+    pub fn monitor(&self, name: &str) -> Result<LogsReport, GsmError> {
+        let server = self.find(name)?;
 
-        if let Some(index) = index {
-            self.servers[index].monitor()?;
-            Ok(())
-        } else {
-            Err("Server not found".into())
+        Ok(LogsReport {
+            name: name.to_string(),
+            log: server.monitor()?,
+        })
+    }
+
+    pub fn clear_logs(&mut self, name: &str) -> Result<(), GsmError> {
+        let index = self.position(name)?;
+        self.servers[index].clear_logs()
+    }
+
+
+    pub fn update(&mut self, name: &str) -> Result<(), GsmError> {
+        let index = self.position(name)?;
+
+        let result = self.servers[index].update();
+        self.backup()?;
+        result
+    }
+
+    pub fn deploy(&mut self, name: &str, rev: &str) -> Result<(), GsmError> {
+        let index = self.position(name)?;
+
+        let result = self.servers[index].deploy(rev);
+        self.backup()?;
+        result
+    }
+
+    pub fn rollback(&mut self, name: &str) -> Result<(), GsmError> {
+        let index = self.position(name)?;
+
+        let result = self.servers[index].rollback();
+        self.backup()?;
+        result
+    }
+
+    pub fn enable_releases(&mut self, name: &str, root: &Path, keep: u32) -> Result<(), GsmError> {
+        let index = self.position(name)?;
+
+        let mut server = self.servers[index].clone();
+        enable_releases(&mut server, root, keep)?;
+        self.check_unique(&server, Some(name))?;
+        self.servers[index] = server;
+        self.backup()?;
+        Ok(())
+    }
+
+    pub fn list_releases(&self, name: &str) -> Result<ReleasesReport, GsmError> {
+        let server = self.find(name)?;
+        let root = server.release_root.as_ref()
+            .ok_or_else(|| GsmError::InvalidState(String::from("Releases are not enabled for this server.")))?;
+
+        Ok(ReleasesReport {
+            name: name.to_string(),
+            keep: server.keep_releases,
+            live: std::fs::read_link(root.join("current"))?,
+            releases: list_releases(root)?,
+        })
+    }
+
+    pub fn enable_blue_green(&mut self, name: &str, green_port: u32, upstream: Option<PathBuf>, proxy_reload: Option<String>) -> Result<(), GsmError> {
+        let index = self.position(name)?;
+
+        let mut server = self.servers[index].clone();
+        if server.name.to_lowercase().contains("redis-server") {
+            return Err(GsmError::InvalidArgument(String::from("Blue/green is not supported for Redis servers.")));
+        }
+        if server.running {
+            return Err(GsmError::InvalidState(String::from("Stop the server before enabling blue/green.")));
         }
+        server.blue_green = Some(BlueGreen {
+            green_port,
+            active: Color::Blue,
+            upstream,
+            proxy_reload,
+        });
+        self.check_unique(&server, Some(name))?;
+
+        self.servers[index] = server;
+        self.backup()?;
+        eprintln!("Blue/green enabled for [{}], blue on port {} and green on port {}", name, self.servers[index].port, green_port);
+        Ok(())
     }
 
-    pub fn clear_logs(&mut self, name: &str) -> Result<(), Box<dyn Error>> {
-        let index = self.servers.iter().position(|s| s.name == name);
+    pub fn disable_blue_green(&mut self, name: &str) -> Result<(), GsmError> {
+        let index = self.position(name)?;
 
-        if let Some(index) = index {
-            self.servers[index].clear_logs()?;
-            Ok(())
-        } else {
-            Err("Server not found".into())
+        if self.servers[index].running {
+            return Err(GsmError::InvalidState(String::from("Stop the server before disabling blue/green.")));
         }
+        self.servers[index].blue_green = None;
+        self.backup()?;
+        eprintln!("Blue/green disabled for [{}]", name);
+        Ok(())
     }
 
+    // Moves traffic to the idle color without deploying new code
+    pub fn switch_colors(&mut self, name: &str) -> Result<(), GsmError> {
+        let index = self.position(name)?;
 
-    ////////////////////////////////////
-    pub fn update(&mut self, name: &str) {
-        let index = self.servers.iter().position(|s| s.name == name);
+        if !self.servers[index].running {
+            return Err(GsmError::InvalidState(String::from("Server not currently running.")));
+        }
+        let result = switch_colors(&mut self.servers[index]);
+        self.backup()?;
+        result
+    }
 
-        if let Some(index) = index {
-            self.servers[index].update();
-            self.backup();
-        } else {
-            println!("Server not found.")
+    pub fn enable_auto_update(&mut self, name: &str, interval: u64, window: Option<MaintenanceWindow>) -> Result<(), GsmError> {
+        let index = self.position(name)?;
+
+        if !self.servers[index].github {
+            return Err(GsmError::InvalidState(String::from("Auto update requires the server directory to be a git repository.")));
+        }
+        if interval == 0 {
+            return Err(GsmError::InvalidArgument(String::from("Poll interval must be at least one second.")));
         }
+        self.servers[index].auto_update = Some(AutoUpdate { interval, window });
+        self.backup()?;
+        match window {
+            Some(window) => eprintln!("Auto update enabled for [{}], polling every {}s, updating during {}", name, interval, window),
+            None => eprintln!("Auto update enabled for [{}], polling every {}s", name, interval),
+        }
+        Ok(())
     }
 
-    pub fn git_init(&mut self, name: &str) {
-        let index = self.servers.iter().position(|s| s.name == name);
+    pub fn disable_auto_update(&mut self, name: &str) -> Result<(), GsmError> {
+        let index = self.position(name)?;
 
-        if let Some(index) = index {
-            // Safely shut down the server before removing
-            self.servers[index].git_init();
-            self.backup();
-        } else {
-            println!("Server not found.")
+        self.servers[index].auto_update = None;
+        self.backup()?;
+        eprintln!("Auto update disabled for [{}]", name);
+        Ok(())
+    }
+
+    // Polls one server and updates it if new commits arrived, returns whether it was updated.
+    // Outside of the maintenance window the server is not polled at all.
+    pub fn poll_update(&mut self, name: &str, now: u64) -> Result<bool, GsmError> {
+        let server = self.find(name)?;
+        let auto_update = server.auto_update.as_ref()
+            .ok_or_else(|| GsmError::InvalidState(String::from("Auto update is not enabled for this server.")))?;
+
+        if let Some(window) = auto_update.window {
+            if !window.contains(utc_minute(now)) {
+                return Ok(false);
+            }
         }
+        if !server.has_pending_commits()? {
+            return Ok(false);
+        }
+
+        eprintln!("[{}] New commits found, updating...", name);
+        self.update(name)?;
+        Ok(true)
     }
 
-    pub fn add_origin(&mut self, name: &str, remote_url: &str) {
-        let index = self.servers.iter().position(|s| s.name == name);
+    // Runs a single poll of every server with auto update enabled, for cron jobs
+    pub fn check_updates(&mut self, now: u64) {
+        let names = self.servers.iter()
+            .filter(|server| server.auto_update.is_some())
+            .map(|server| server.name.clone())
+            .collect::<Vec<_>>();
 
-        if let Some(index) = index {
-            // Safely shut down the server before removing
-            self.servers[index].git_set_origin(remote_url);
-        } else {
-            println!("Server not found.")
+        for name in names {
+            match self.poll_update(&name, now) {
+                Ok(true) => {},
+                Ok(false) => eprintln!("[{}] Nothing to update.", name),
+                Err(e) => eprintln!("[ERROR] [{}] {}", name, e),
+            }
         }
     }
 
+    pub fn git_init(&mut self, name: &str) -> Result<(), GsmError> {
+        let index = self.position(name)?;
+
+        self.servers[index].git_init()?;
+        self.backup()
+    }
+
+    pub fn add_origin(&mut self, name: &str, remote_url: &str) -> Result<(), GsmError> {
+        let index = self.position(name)?;
+
+        self.servers[index].git_set_origin(remote_url)
+    }
+
+    pub fn git_status(&self, name: &str) -> Result<GitStatusReport, GsmError> {
+        let server = self.find(name)?;
+
+        Ok(GitStatusReport {
+            name: name.to_string(),
+            status: server.git_status()?,
+        })
+    }
+
+    // Helper function to check that a server's name, path and port are not taken,
+    // optionally skipping the server named `exclude`
+    fn check_unique(&self, server: &Server, exclude: Option<&str>) -> Result<(), GsmError> {
+        if exclude.is_none() && self.name_exists(&server.name) {
+            return Err(GsmError::NameTaken(server.name.clone()));
+        }
+
+        if self.path_exists(&server.path, exclude) {
+            return Err(GsmError::PathInUse(server.path.clone()));
+        }
+
+        if let Some(port) = server.ports().into_iter().find(|port| self.port_exists(*port, exclude)) {
+            return Err(GsmError::PortInUse(port));
+        }
+
+        if let Some(blue_green) = &server.blue_green {
+            if blue_green.green_port == server.port {
+                return Err(GsmError::PortInUse(blue_green.green_port));
+            }
+        }
+
+        Ok(())
+    }
+
+    // Names of the servers matching `name` (exact or glob) and carrying every one of `tags`,
+    // or of all servers
+    pub fn select(&self, name: Option<&str>, tags: &[String], all: bool) -> Result<Vec<String>, GsmError> {
+        if all {
+            return Ok(self.servers.iter().map(|s| s.name.clone()).collect());
+        }
+        if name.is_none() && tags.is_empty() {
+            return Err(GsmError::InvalidArgument(String::from("Specify servers with --name, --tag or --all")));
+        }
+
+        let pattern = match name {
+            Some(name) if name.contains(['*', '?', '[']) => Some(
+                Pattern::new(name).map_err(|e| GsmError::InvalidArgument(format!("Invalid name pattern '{}': {}", name, e)))?
+            ),
+            Some(name) if !self.name_exists(name) => return Err(GsmError::NotFound(name.to_string())),
+            _ => None,
+        };
+
+        let selected = self.servers.iter()
+            .filter(|s| match (&pattern, name) {
+                (Some(pattern), _) => pattern.matches(&s.name),
+                (None, Some(name)) => s.name == name,
+                (None, None) => true,
+            })
+            .filter(|s| tags.iter().all(|tag| s.tags.contains(tag)))
+            .map(|s| s.name.clone())
+            .collect::<Vec<_>>();
+        if selected.is_empty() {
+            return Err(GsmError::InvalidArgument(String::from("No servers match")));
+        }
+        Ok(selected)
+    }
+
     // Helper function to check if a server name already exists
     pub fn name_exists(&self, name: &str) -> bool {
         self.servers.iter().any(|s| s.name == name)
     }
 
     // Helper function to check if a server path already exists
-    fn path_exists(&self, path: &Path) -> bool {
-        self.servers.iter().any(|s| s.path == path)
+    fn path_exists(&self, path: &Path, exclude: Option<&str>) -> bool {
+        self.servers.iter().any(|s| s.path == path && Some(s.name.as_str()) != exclude)
     }
 
     // Helper function to check if a server port already exists
-    fn port_exists(&self, port: u32) -> bool {
-        self.servers.iter().any(|s| s.port == port)
+    fn port_exists(&self, port: u32, exclude: Option<&str>) -> bool {
+        self.servers.iter().any(|s| s.ports().contains(&port) && Some(s.name.as_str()) != exclude)
     }
 
-    pub fn backup(&self) {
+    pub fn backup(&self) -> Result<(), GsmError> {
+        self.backup_to(&state::state_file())
+    }
+
+    // Makes this instance the only one writing `path` for as long as it lives. Claiming it
+    // when starting, not at the first write, rejects a second writer before it changes anything.
+    pub fn claim(&self, path: &Path) -> Result<(), GsmError> {
+        let mut claims = self.claims.lock().unwrap_or_else(|e| e.into_inner());
+        if !claims.iter().any(|claim| claim.path() == path) {
+            if let Some(dir) = path.parent() {
+                fs::create_dir_all(dir)
+                    .map_err(|e| GsmError::Backup(format!("Failed to create state directory: {}", e)))?;
+            }
+            claims.push(state::claim(path)?);
+        }
+        Ok(())
+    }
+
+    // Saves the servers to `path`, then to the last good copy `restore_from` falls back on
+    pub fn backup_to(&self, path: &Path) -> Result<(), GsmError> {
+        self.claim(path)?;
         let servers_data: Vec<ServerData> = self.servers.iter().map(ServerData::from).collect();
 
-        let json = serde_json::to_string(&servers_data).expect("Failed to serialize servers");
+        let json = serde_json::to_string(&servers_data)
+            .map_err(|e| GsmError::Backup(format!("Failed to serialize servers: {}", e)))?;
+
+        // The state being replaced becomes the last good copy, unless it's corrupt itself
+        if let Ok(previous) = fs::read(path) {
+            if serde_json::from_slice::<Vec<ServerData>>(&previous).is_ok() {
+                let last_good = state::last_good(path);
+                state::write_atomic(&last_good, &previous)
+                    .map_err(|e| GsmError::Backup(format!("Failed to write to backup file {}: {}", last_good.display(), e)))?;
+            }
+        }
+        state::write_atomic(path, json.as_bytes())
+            .map_err(|e| GsmError::Backup(format!("Failed to write to backup file {}: {}", path.display(), e)))?;
+        snapshots::record(&snapshots::snapshots_dir(path), servers_data)?;
+        Ok(())
+    }
 
-        let mut file = File::create("backups/servers_backup.json").expect("Failed to create backup file");
-        file.write_all(json.as_bytes()).expect("Failed to write to backup file");
+    pub fn restore(&mut self) -> Result<(), GsmError> {
+        self.restore_from(&state::state_file())
     }
 
-    pub fn restore(&mut self) {
-        let backup_path = Path::new("backups/servers_backup.json");
+    // Loads the servers saved in `path`, or in its last good copy if it can't be read
+    pub fn restore_from(&mut self, path: &Path) -> Result<(), GsmError> {
+        let last_good = state::last_good(path);
 
         // Check if the backup file exists
-        if !backup_path.exists() {
-            println!("No back up file found. Skipping restoration process.");
-            return;
+        if !path.exists() && !last_good.exists() {
+            eprintln!("No back up file found. Skipping restoration process.");
+            return Ok(());
         }
 
-        let mut file = File::open(&backup_path)
-            .expect("Failed to open backup file");
-        let mut json = String::new();
-        file.read_to_string(&mut json).expect("Failed to read from backup file");
-
-        let servers_data: Vec<ServerData> = serde_json::from_str(&json).expect("Failed to deserialize servers");
+        // Writes are atomic renames, so reading needs no lock
+        let servers_data = match read_backup(path) {
+            Ok(servers_data) => servers_data,
+            Err(e) => {
+                let servers_data = read_backup(&last_good).map_err(|_| GsmError::Backup(e.clone()))?;
+                eprintln!("[WARNING] {}, restored the last good copy {}", e, last_good.display());
+                servers_data
+            },
+        };
         self.servers = servers_data.into_iter().map(|data| data.into()).collect();
+        Ok(())
+    }
+
+    pub fn list_snapshots(&self) -> Result<Vec<SnapshotInfo>, GsmError> {
+        snapshots::list(&snapshots::snapshots_dir(&state::state_file()))
+    }
+
+    // Changes restoring snapshot `id` makes to the servers, as printed before restoring it
+    pub fn snapshot_diff(&self, id: u64) -> Result<Vec<String>, GsmError> {
+        let snapshot = snapshots::load(&snapshots::snapshots_dir(&state::state_file()), id)?;
+        Ok(snapshots::diff(&self.servers, &snapshot))
     }
 
-    pub fn visualize(&self, name: &str, show: &bool) {
-        let index = self.servers.iter().position(|s| s.name == name);
+    // Restores snapshot `id`, running servers it doesn't have are stopped first
+    pub fn restore_snapshot(&mut self, id: u64) -> Result<(), GsmError> {
+        let snapshot = snapshots::load(&snapshots::snapshots_dir(&state::state_file()), id)?;
+        let removed = self.servers.iter()
+            .filter(|server| server.running && !snapshot.contains(&server.name))
+            .map(|server| server.name.clone())
+            .collect::<Vec<_>>();
+        for name in removed {
+            eprintln!("Stopping [{}], snapshot {} doesn't have it", name, id);
+            let index = self.position(&name)?;
+            let result = self.servers[index].stop();
+            self.backup()?;
+            result?;
+        }
+
+        self.apply_snapshot(snapshot)?;
+        self.backup()?;
+        eprintln!("Restored snapshot {}, servers added back are stopped", id);
+        for server in self.servers.iter().filter(|server| server.running_address.is_some()) {
+            eprintln!("[INFO] [{}] still serves its previous address, restart it to apply the restored one", server.name);
+        }
+        Ok(())
+    }
+
+    // Replaces the servers with the ones of `snapshot`, those still defined keep their running state.
+    // Running servers are never dropped, and the ones whose bind or port changes remember the
+    // address they serve until they are restarted
+    pub fn apply_snapshot(&mut self, snapshot: Snapshot) -> Result<(), GsmError> {
+        if let Some(server) = self.servers.iter().find(|server| server.running && !snapshot.contains(&server.name)) {
+            return Err(GsmError::InvalidState(format!("[{}] is running and not in the snapshot, stop it first", server.name)));
+        }
+
+        self.servers = snapshot.servers().into_iter()
+            .map(|mut server| {
+                match self.servers.iter().find(|current| current.name == server.name) {
+                    Some(current) => {
+                        server.running = current.running;
+                        server.keep_running_address(current);
+                    },
+                    None => {
+                        server.running = false;
+                        server.running_address = None;
+                    },
+                }
+                server
+            })
+            .collect();
+        Ok(())
+    }
+
+    pub fn visualize(&self, name: &str, show: &bool) -> Result<(), GsmError> {
+        let server = self.find(name)?;
         let show_arg = if *show {
             String::from("True")
         } else {
             String::from("False")
         };
-        if let Some(index) = index {
-            let log_filename = format!("{}.log", self.servers[index].name);
-            let log_path = self.servers[index].log_path.join(&log_filename);
-
-            if log_path.exists() {
-                let output = Command::new("python")
-                .arg("scripts/main.py")
-                .arg(log_path)
-                .arg(show_arg)
-                .output()
-                .expect("Failed to execute Python script");
-                if output.status.success() {
-                    let stdout = String::from_utf8_lossy(&output.stdout);
-                    let stderr = String::from_utf8_lossy(&output.stderr);
-    
-                    println!("Python script executed successfully:");
-                    println!("Output:{}", stdout);
-                    println!("Errors:{}", stderr);
-                } else {
-                    let stderr = String::from_utf8_lossy(&output.stderr);
-                    eprintln!("Failed to execute Python script:");
-                    eprintln!("{}", stderr);
-                }
-            } else {
-                println!("Log file unavailable.")
-            }
-        } else {
-            println!("Server not found.")
+
+        let log_path = server.original_dir.join(&server.log_path).join(format!("{}.log", server.name));
+        if !log_path.exists() {
+            return Err(GsmError::InvalidState(String::from("Log file unavailable.")));
+        }
+
+        // The script writes its csv files and figures under `data/` of the working directory
+        let state_dir = state::state_dir();
+        fs::create_dir_all(state_dir.join("data").join("logs"))?;
+        fs::create_dir_all(state_dir.join("data").join("figures"))?;
+        let scripts = install_scripts(state_dir)?;
+
+        let output = Command::new("python")
+            .arg(scripts.join("main.py"))
+            .arg(log_path)
+            .arg(show_arg)
+            .current_dir(state_dir)
+            .output()?;
+        if !output.status.success() {
+            return Err(GsmError::SpawnFailed {
+                action: String::from("execute Python script"),
+                stderr: String::from_utf8_lossy(&output.stderr).into_owned(),
+            });
         }
+
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        eprintln!("Python script executed successfully:");
+        eprintln!("Output:{}", stdout);
+        eprintln!("Errors:{}", stderr);
+        Ok(())
     }
     
 }
 
+// Writes the visualization scripts under `dir`, leaving the ones already up to date alone
+fn install_scripts(dir: &Path) -> Result<PathBuf, GsmError> {
+    let scripts = dir.join("scripts");
+    fs::create_dir_all(&scripts)?;
+    for (name, source) in SCRIPTS {
+        let path = scripts.join(name);
+        if fs::read_to_string(&path).ok().as_deref() != Some(source) {
+            fs::write(&path, source)?;
+        }
+    }
+    Ok(scripts)
+}
+
+fn read_backup(path: &Path) -> Result<Vec<ServerData>, String> {
+    let mut file = File::open(path)
+        .map_err(|e| format!("Failed to open backup file {}: {}", path.display(), e))?;
+    let mut json = String::new();
+    file.read_to_string(&mut json)
+        .map_err(|e| format!("Failed to read from backup file {}: {}", path.display(), e))?;
+
+    serde_json::from_str(&json)
+        .map_err(|e| format!("Failed to deserialize servers from {}: {}", path.display(), e))
+}
+
 #[derive(Serialize, Deserialize)]
-struct ServerData {
-    name: String,
-    path: String,
+pub(crate) struct ServerData {
+    pub(crate) name: String,
+    path: PathBuf,
     bind: String,
     port: u32,
     workers: u32,
@@ -297,43 +1010,111 @@ struct ServerData {
     running: bool,
     framework: String,
     original_dir: PathBuf,
-    on_command: String
+    on_command: String,
+    #[serde(default)]
+    env: BTreeMap<String, String>,
+    #[serde(default)]
+    env_file: Option<PathBuf>,
+    #[serde(default)]
+    venv: Option<PathBuf>,
+    #[serde(default)]
+    build_steps: Vec<BuildStep>,
+    #[serde(default)]
+    deployed_commit: Option<String>,
+    #[serde(default)]
+    previous_commit: Option<String>,
+    #[serde(default)]
+    branch: Option<String>,
+    #[serde(default)]
+    release_root: Option<PathBuf>,
+    #[serde(default = "default_keep_releases")]
+    keep_releases: u32,
+    #[serde(default)]
+    blue_green: Option<BlueGreen>,
+    #[serde(default)]
+    auto_update: Option<AutoUpdate>,
+    #[serde(default)]
+    app: Option<String>,
+    #[serde(default)]
+    hooks: BTreeMap<HookKind, Hook>,
+    #[serde(default)]
+    tags: Vec<String>,
+    #[serde(default)]
+    depends_on: Vec<String>,
+    #[serde(default)]
+    running_address: Option<(String, u32)>,
+}
+
+fn default_keep_releases() -> u32 {
+    5
 }
 
 impl From<&Server> for ServerData {
     fn from(server: &Server) -> Self {
         Self {
             name: server.name.clone(),
-            path: server.path.to_str().unwrap().to_string(),
+            path: server.path.clone(),
             bind: server.bind.clone(),
             port: server.port,
             workers: server.workers,
             timeout: server.timeout,
-            log_path: server.log_path.to_str().unwrap().into(),
+            log_path: server.log_path.clone(),
             github: server.github,
             running: server.running,
             framework: server.framework.clone(),
             original_dir: server.original_dir.clone(),
-            on_command: server.on_command.clone()
+            on_command: server.on_command.clone(),
+            env: server.env.clone(),
+            env_file: server.env_file.clone(),
+            venv: server.venv.clone(),
+            build_steps: server.build_steps.clone(),
+            deployed_commit: server.deployed_commit.clone(),
+            previous_commit: server.previous_commit.clone(),
+            branch: server.branch.clone(),
+            release_root: server.release_root.clone(),
+            keep_releases: server.keep_releases,
+            blue_green: server.blue_green.clone(),
+            auto_update: server.auto_update.clone(),
+            app: server.app.clone(),
+            hooks: server.hooks.clone(),
+            tags: server.tags.clone(),
+            depends_on: server.depends_on.clone(),
+            running_address: server.running_address.clone(),
         }
     }
 }
 
-impl Into<Server> for ServerData {
-    fn into(self) -> Server {
-        Server {
-            name: self.name,
-            path: PathBuf::from(self.path),
-            bind: self.bind,
-            port: self.port,
-            workers: self.workers,
-            timeout: self.timeout,
-            log_path: PathBuf::from(self.log_path),
-            github: self.github,
-            running: self.running,
-            framework: self.framework,
-            original_dir: self.original_dir,
-            on_command: self.on_command
+impl From<ServerData> for Server {
+    fn from(data: ServerData) -> Self {
+        Self {
+            name: data.name,
+            path: data.path,
+            bind: data.bind,
+            port: data.port,
+            workers: data.workers,
+            timeout: data.timeout,
+            log_path: data.log_path,
+            github: data.github,
+            running: data.running,
+            framework: data.framework,
+            original_dir: data.original_dir,
+            on_command: data.on_command,
+            env: data.env,
+            env_file: data.env_file,
+            venv: data.venv,
+            build_steps: data.build_steps,
+            deployed_commit: data.deployed_commit,
+            previous_commit: data.previous_commit,
+            branch: data.branch,
+            release_root: data.release_root,
+            keep_releases: data.keep_releases,
+            blue_green: data.blue_green,
+            auto_update: data.auto_update,
+            app: data.app,
+            hooks: data.hooks,
+            tags: data.tags,
+            depends_on: data.depends_on,
+            running_address: data.running_address,
         }
     }
 }
\ No newline at end of file
@@ -0,0 +1,205 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use serde::{Serialize, Deserialize};
+use serde_json::Value;
+use crate::server::server::Server;
+use crate::server::servers::ServerData;
+use crate::error::GsmError;
+use crate::utils::output::Report;
+use crate::utils::state;
+use crate::github::poller::now_secs;
+
+// Snapshots kept, the oldest ones are removed first
+pub const MAX_SNAPSHOTS: usize = 50;
+
+// Server settings that change with the processes rather than the configuration, so starting or
+// stopping servers neither records a snapshot nor shows in a diff
+const RUNTIME_KEYS: [&str; 3] = ["running", "running_address", "on_command"];
+
+// Servers as saved by one backup event
+#[derive(Serialize, Deserialize)]
+pub struct Snapshot {
+    pub id: u64,
+    pub timestamp: u64, // Unix seconds
+    pub command: String, // Command that triggered the backup
+    servers: Vec<ServerData>,
+}
+
+impl Snapshot {
+    pub fn contains(&self, name: &str) -> bool {
+        self.servers.iter().any(|data| data.name == name)
+    }
+
+    pub fn servers(self) -> Vec<Server> {
+        self.servers.into_iter().map(|data| data.into()).collect()
+    }
+}
+
+// One snapshot as shown by `snapshots list`
+#[derive(Debug, Serialize)]
+pub struct SnapshotInfo {
+    pub id: u64,
+    pub timestamp: u64,
+    pub command: String,
+    pub servers: Vec<String>, // Names of the saved servers
+}
+
+impl Report for Vec<SnapshotInfo> {
+    fn print_text(&self) {
+        if self.is_empty() {
+            println!("[INFO] No snapshots yet");
+        }
+        for snapshot in self {
+            println!("[{}] {} UTC | Command: {} | Servers: [{}] |",
+                snapshot.id,
+                format_utc(snapshot.timestamp),
+                if snapshot.command.is_empty() { "-" } else { &snapshot.command },
+                snapshot.servers.join(", ")
+            );
+        }
+    }
+}
+
+// Snapshots directory next to the state file
+pub fn snapshots_dir(state_file: &Path) -> PathBuf {
+    state_file.with_file_name("snapshots")
+}
+
+// Saves `servers` as the newest snapshot and drops the ones past `MAX_SNAPSHOTS`. Nothing is
+// saved when only their running state changed since the newest one, whose id is returned then.
+pub(crate) fn record(dir: &Path, servers: Vec<ServerData>) -> Result<u64, GsmError> {
+    fs::create_dir_all(dir)
+        .map_err(|e| GsmError::Backup(format!("Failed to create snapshots directory: {}", e)))?;
+    let ids = snapshot_ids(dir)?;
+    if let Some(&latest) = ids.last() {
+        // A snapshot that can't be read is replaced rather than compared
+        if load(dir, latest).is_ok_and(|snapshot| configuration(&snapshot.servers) == configuration(&servers)) {
+            return Ok(latest);
+        }
+    }
+    let snapshot = Snapshot {
+        id: ids.last().map(|id| id + 1).unwrap_or(1),
+        timestamp: now_secs(),
+        command: state::command(),
+        servers,
+    };
+
+    let json = serde_json::to_string(&snapshot)
+        .map_err(|e| GsmError::Backup(format!("Failed to serialize snapshot: {}", e)))?;
+    state::write_atomic(&snapshot_path(dir, snapshot.id), json.as_bytes())
+        .map_err(|e| GsmError::Backup(format!("Failed to write snapshot {}: {}", snapshot.id, e)))?;
+
+    let excess = (ids.len() + 1).saturating_sub(MAX_SNAPSHOTS);
+    for id in &ids[..excess] {
+        fs::remove_file(snapshot_path(dir, *id))
+            .map_err(|e| GsmError::Backup(format!("Failed to remove snapshot {}: {}", id, e)))?;
+    }
+    Ok(snapshot.id)
+}
+
+// Saved snapshots, oldest first
+pub fn list(dir: &Path) -> Result<Vec<SnapshotInfo>, GsmError> {
+    snapshot_ids(dir)?.into_iter()
+        .map(|id| load(dir, id).map(|snapshot| SnapshotInfo {
+            id: snapshot.id,
+            timestamp: snapshot.timestamp,
+            command: snapshot.command,
+            servers: snapshot.servers.iter().map(|data| data.name.clone()).collect(),
+        }))
+        .collect()
+}
+
+pub fn load(dir: &Path, id: u64) -> Result<Snapshot, GsmError> {
+    let path = snapshot_path(dir, id);
+    if !path.exists() {
+        return Err(GsmError::InvalidArgument(format!("Snapshot {} not found, see `snapshots list`", id)));
+    }
+    let json = fs::read_to_string(&path)
+        .map_err(|e| GsmError::Backup(format!("Failed to read snapshot {}: {}", id, e)))?;
+    serde_json::from_str(&json)
+        .map_err(|e| GsmError::Backup(format!("Failed to deserialize snapshot {}: {}", id, e)))
+}
+
+// Changes restoring `snapshot` makes to `current`: `+` for servers added back,
+// `-` for servers removed and `~` for each changed setting
+pub fn diff(current: &[Server], snapshot: &Snapshot) -> Vec<String> {
+    let current = entries(&current.iter().map(ServerData::from).collect::<Vec<_>>());
+    let restored = entries(&snapshot.servers);
+
+    let mut lines = Vec::new();
+    for name in current.keys().filter(|name| !restored.contains_key(*name)) {
+        lines.push(format!("- {}", name));
+    }
+    for (name, entry) in &restored {
+        match current.get(name) {
+            None => lines.push(format!("+ {}", name)),
+            Some(old) => {
+                // Restored servers keep their current running state
+                for (key, value) in entry.as_object().into_iter().flatten().filter(|(key, _)| !RUNTIME_KEYS.contains(&key.as_str())) {
+                    let old_value = &old[key.as_str()];
+                    if old_value != value {
+                        lines.push(format!("~ {} {}: {} -> {}", name, key, old_value, value));
+                    }
+                }
+            },
+        }
+    }
+    lines
+}
+
+// Server entries by name as JSON, so any setting can be compared
+fn entries(servers: &[ServerData]) -> BTreeMap<String, Value> {
+    servers.iter()
+        .map(|data| (data.name.clone(), serde_json::to_value(data).unwrap_or(Value::Null)))
+        .collect()
+}
+
+// Server entries without their runtime keys
+fn configuration(servers: &[ServerData]) -> BTreeMap<String, Value> {
+    let mut entries = entries(servers);
+    for entry in entries.values_mut() {
+        if let Some(object) = entry.as_object_mut() {
+            for key in RUNTIME_KEYS {
+                object.remove(key);
+            }
+        }
+    }
+    entries
+}
+
+fn snapshot_path(dir: &Path, id: u64) -> PathBuf {
+    dir.join(format!("{}.json", id))
+}
+
+fn snapshot_ids(dir: &Path) -> Result<Vec<u64>, GsmError> {
+    if !dir.exists() {
+        return Ok(Vec::new());
+    }
+    let mut ids = fs::read_dir(dir)
+        .map_err(|e| GsmError::Backup(format!("Failed to read snapshots directory: {}", e)))?
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| entry.path().file_stem()?.to_str()?.parse::<u64>().ok())
+        .collect::<Vec<_>>();
+    ids.sort();
+    Ok(ids)
+}
+
+// `YYYY-MM-DD HH:MM:SS` of Unix seconds, in UTC
+fn format_utc(secs: u64) -> String {
+    let days = (secs / 86400) as i64;
+    let time = secs % 86400;
+
+    // Days since the epoch to a civil date, see http://howardhinnant.github.io/date_algorithms.html
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    format!("{:04}-{:02}-{:02} {:02}:{:02}:{:02}", year, month, day, time / 3600, time / 60 % 60, time % 60)
+}
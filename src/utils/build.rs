@@ -1,42 +1,147 @@
-use std::env;
-use std::path::Path;
-use std::process::Command;
+use std::collections::BTreeMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::os::unix::process::CommandExt;
+use std::process::{Command, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+use glob::{MatchOptions, Pattern};
+use nix::sys::signal::{killpg, Signal};
+use nix::unistd::Pid;
+use serde::{Serialize, Deserialize};
+use crate::server::server::Server;
 
-pub fn contains_compiled_files(diff_output: &str) -> bool {
-    diff_output.contains("CMakeLists.txt") || diff_output.contains("src")
+// A single command of a server's build pipeline
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct BuildStep {
+    pub command: String, // Shell command to run
+    #[serde(default)]
+    pub dir: Option<PathBuf>, // Working directory, relative to the server directory
+    #[serde(default)]
+    pub env: BTreeMap<String, String>, // Extra environment variables for this step
+    #[serde(default)]
+    pub timeout: Option<u64>, // Seconds before the step is killed, no limit by default
+    #[serde(default)]
+    pub patterns: Vec<String>, // Glob patterns on changed files that trigger the step, always runs if empty
 }
 
-pub fn run_cmake(path: &Path) -> Result<(), String> {
-    env::set_current_dir(Path::new(path).join("build"))
-        .map_err(|e| format!("Failed to navigate to build directory: {}", e))?;
+impl BuildStep {
+    // Whether any of the changed files matches the step's patterns
+    pub fn is_triggered(&self, changed_files: &[&str]) -> Result<bool, String> {
+        if self.patterns.is_empty() {
+            return Ok(true);
+        }
 
-    Command::new("cmake")
-        .arg("..")
-        .status()
-        .map_err(|e| format!("Failed to run cmake: {}", e))?;
+        let options = MatchOptions {
+            case_sensitive: true,
+            require_literal_separator: true,
+            require_literal_leading_dot: false,
+        };
+        for pattern in &self.patterns {
+            let pattern = Pattern::new(pattern)
+                .map_err(|e| format!("Invalid build pattern '{}': {}", pattern, e))?;
+            if changed_files.iter().any(|file| pattern.matches_with(file, options)) {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+}
+
+// The cmake/make pipeline used for C++ projects that don't define their own steps
+pub fn default_build_steps(path: &Path) -> Vec<BuildStep> {
+    if !path.join("CMakeLists.txt").exists() {
+        return Vec::new();
+    }
 
-    env::set_current_dir(path)
-        .map_err(|e| format!("Failed to return to the original directory: {}", e))?;
+    let step = |command: &str, patterns: &[&str]| BuildStep {
+        command: String::from(command),
+        dir: Some(PathBuf::from("build")),
+        env: BTreeMap::new(),
+        timeout: None,
+        patterns: patterns.iter().map(|p| p.to_string()).collect(),
+    };
+    let sources = ["**/CMakeLists.txt", "src/**", "**/src/**"];
 
-    Ok(())
+    vec![
+        step("cmake ..", &["**/CMakeLists.txt"]),
+        step("make -j4", &sources),
+        step("make install", &sources),
+    ]
 }
 
-pub fn compile_and_install_project(path: &Path) -> Result<(), String> {
-    env::set_current_dir(Path::new(path).join("build"))
-        .map_err(|e| format!("Failed to navigate to build directory: {}", e))?;
+// Runs the build steps triggered by `changed_files` (every step if `None`) in order,
+// appending their output to `log_file`. Stops at the first failing step.
+pub fn run_build_steps(server: &Server, steps: &[BuildStep], changed_files: Option<&[&str]>, log_file: &Path) -> Result<usize, String> {
+    let vars = server.command_environment().map_err(|e| e.to_string())?;
+
+    let mut log = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(log_file)
+        .map_err(|e| format!("Failed to open build log {}: {}", log_file.display(), e))?;
 
-    Command::new("make")
-        .arg("-j4")
-        .status()
-        .map_err(|e| format!("Failed to run make: {}", e))?;
+    let mut ran = 0;
+    for step in steps {
+        if let Some(changed_files) = changed_files {
+            if !step.is_triggered(changed_files)? {
+                continue;
+            }
+        }
 
-    Command::new("make")
-        .arg("install")
-        .status()
-        .map_err(|e| format!("Failed to run make install: {}", e))?;
+        let path = server.original_dir.join(&server.path);
+        let dir = match &step.dir {
+            Some(dir) => path.join(dir),
+            None => path,
+        };
+        fs::create_dir_all(&dir)
+            .map_err(|e| format!("Failed to create build directory {}: {}", dir.display(), e))?;
 
-    env::set_current_dir(path)
-        .map_err(|e| format!("Failed to return to the original directory: {}", e))?;
+        eprintln!("Running `{}` in {}...", step.command, dir.display());
+        writeln!(log, "$ cd {} && {}", dir.display(), step.command)
+            .map_err(|e| format!("Failed to write build log: {}", e))?;
+
+        run_step(step, &dir, &vars, &log)?;
+        ran += 1;
+    }
+    Ok(ran)
+}
 
-    Ok(())
-}
\ No newline at end of file
+fn run_step(step: &BuildStep, dir: &Path, vars: &BTreeMap<String, String>, log: &File) -> Result<(), String> {
+    let stdout = log.try_clone().map_err(|e| format!("Failed to open build log: {}", e))?;
+    let stderr = log.try_clone().map_err(|e| format!("Failed to open build log: {}", e))?;
+
+    let mut child = Command::new("sh")
+        .arg("-c")
+        .arg(&step.command)
+        .current_dir(dir)
+        .envs(vars)
+        .envs(&step.env)
+        .stdout(Stdio::from(stdout))
+        .stderr(Stdio::from(stderr))
+        // Own process group, so a timeout also kills the commands started by the shell
+        .process_group(0)
+        .spawn()
+        .map_err(|e| format!("Failed to run `{}`: {}", step.command, e))?;
+
+    let started = Instant::now();
+    loop {
+        let status = child.try_wait().map_err(|e| format!("Failed to wait for `{}`: {}", step.command, e))?;
+        if let Some(status) = status {
+            if status.success() {
+                return Ok(());
+            }
+            return Err(format!("`{}` failed with {}", step.command, status));
+        }
+
+        if let Some(timeout) = step.timeout {
+            if started.elapsed() >= Duration::from_secs(timeout) {
+                let _ = killpg(Pid::from_raw(child.id() as i32), Signal::SIGKILL);
+                let _ = child.wait();
+                return Err(format!("`{}` timed out after {}s", step.command, timeout));
+            }
+        }
+        thread::sleep(Duration::from_millis(100));
+    }
+}
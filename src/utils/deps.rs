@@ -0,0 +1,56 @@
+use std::path::Path;
+use std::process::Command;
+use crate::server::server::Server;
+
+// Python dependency files whose changes trigger a dependency sync
+pub const DEPENDENCY_FILES: [&str; 4] = ["requirements.txt", "pyproject.toml", "poetry.lock", "Pipfile.lock"];
+
+// Dependency files at the root of the project listed in `git diff --name-only` output
+pub fn changed_dependency_files(diff_output: &str) -> Vec<&'static str> {
+    DEPENDENCY_FILES.iter()
+        .copied()
+        .filter(|file| diff_output.lines().any(|line| line.trim() == *file))
+        .collect()
+}
+
+// Install commands matching the changed dependency files, as program and arguments.
+// `pip` is the pip executable of the server's virtualenv, without one the `pip install`
+// commands are left out so the system Python is never changed.
+pub fn install_commands(changed: &[&str], path: &Path, pip: Option<&str>) -> Vec<Vec<String>> {
+    let mut commands = Vec::new();
+    let to_strings = |args: &[&str]| args.iter().map(|arg| arg.to_string()).collect::<Vec<_>>();
+
+    if changed.contains(&"Pipfile.lock") {
+        commands.push(to_strings(&["pipenv", "sync"]));
+    }
+    if changed.contains(&"poetry.lock") || (changed.contains(&"pyproject.toml") && path.join("poetry.lock").exists()) {
+        commands.push(to_strings(&["poetry", "install", "--no-root"]));
+    } else if let (true, Some(pip)) = (changed.contains(&"pyproject.toml"), pip) {
+        commands.push(to_strings(&[pip, "install", "."]));
+    }
+    if let (true, Some(pip)) = (changed.contains(&"requirements.txt"), pip) {
+        commands.push(to_strings(&[pip, "install", "-r", "requirements.txt"]));
+    }
+    commands
+}
+
+// Runs the install commands in the server directory, inside its virtualenv if it has one
+pub fn sync_dependencies(server: &Server, commands: &[Vec<String>]) -> Result<(), String> {
+    let vars = server.command_environment().map_err(|e| e.to_string())?;
+
+    for command in commands {
+        eprintln!("Running `{}`...", command.join(" "));
+        let output = Command::new(&command[0])
+            .args(&command[1..])
+            .envs(&vars)
+            .current_dir(server.original_dir.join(&server.path))
+            .output()
+            .map_err(|e| format!("Failed to run {}: {}", command[0], e))?;
+
+        if !output.status.success() {
+            let error_message = String::from_utf8_lossy(&output.stderr).trim().to_owned();
+            return Err(format!("`{}` failed: {}", command.join(" "), error_message));
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,213 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// Module names tried first when looking for the application object
+const ENTRY_MODULES: [&str; 6] = ["main", "app", "server", "application", "wsgi", "asgi"];
+
+// Python files at the root of the project, sorted so detection is deterministic
+fn python_files(path: &Path) -> Vec<PathBuf> {
+    let mut files = fs::read_dir(path)
+        .map(|entries| {
+            entries.filter_map(|entry| entry.ok().map(|entry| entry.path()))
+                .filter(|file| file.is_file() && file.extension().and_then(|ext| ext.to_str()) == Some("py"))
+                .collect::<Vec<_>>()
+        })
+        .unwrap_or_default();
+    files.sort();
+    files
+}
+
+// Modules referenced by the `[project.scripts]` or `[tool.poetry.scripts]` tables of `pyproject.toml`
+fn pyproject_modules(path: &Path) -> Vec<String> {
+    let source = match fs::read_to_string(path.join("pyproject.toml")) {
+        Ok(source) => source,
+        Err(_) => return Vec::new(),
+    };
+
+    let mut modules = Vec::new();
+    let mut in_scripts = false;
+    for line in source.lines().map(str::trim) {
+        if line.starts_with('[') {
+            in_scripts = line == "[project.scripts]" || line == "[tool.poetry.scripts]";
+            continue;
+        }
+        if !in_scripts {
+            continue;
+        }
+        if let Some((_, target)) = line.split_once('=') {
+            let target = target.trim().trim_matches(['"', '\'']);
+            if let Some((module, _)) = target.split_once(':') {
+                modules.push(module.trim().to_string());
+            }
+        }
+    }
+    modules
+}
+
+// Source file of a dotted module name, either `a/b.py` or `a/b/__init__.py`
+fn module_file(path: &Path, module: &str) -> Option<PathBuf> {
+    let relative = module.split('.').collect::<PathBuf>();
+    let file = path.join(&relative).with_extension("py");
+    if file.is_file() {
+        return Some(file);
+    }
+    let package = path.join(relative).join("__init__.py");
+    if package.is_file() {
+        Some(package)
+    } else {
+        None
+    }
+}
+
+// Modules that may hold the application: pyproject scripts first, then the root
+// files with the usual entry point names, then every other root file
+fn candidate_modules(path: &Path) -> Vec<(String, PathBuf)> {
+    let mut candidates = pyproject_modules(path).into_iter()
+        .filter_map(|module| module_file(path, &module).map(|file| (module, file)))
+        .collect::<Vec<_>>();
+
+    let mut files = python_files(path).into_iter()
+        .filter_map(|file| {
+            let module = file.file_stem()?.to_str()?.to_string();
+            Some((module, file))
+        })
+        .collect::<Vec<_>>();
+    files.sort_by_key(|(module, _)| ENTRY_MODULES.iter().position(|entry| entry == module).unwrap_or(ENTRY_MODULES.len()));
+
+    for candidate in files {
+        if !candidates.iter().any(|(module, _)| *module == candidate.0) {
+            candidates.push(candidate);
+        }
+    }
+    candidates
+}
+
+fn imports(source: &str, module: &str) -> bool {
+    source.lines().map(str::trim).any(|line| {
+        line.starts_with(&format!("from {} import", module))
+            || line.starts_with(&format!("from {}.", module))
+            || line == format!("import {}", module)
+            || line.starts_with(&format!("import {},", module))
+            || line.starts_with(&format!("import {} ", module))
+    })
+}
+
+// Name of the top level variable assigned from `constructor(...)`, e.g. `app` in `app = Flask(__name__)`
+fn assigned_from(source: &str, constructor: &str) -> Option<String> {
+    source.lines()
+        .filter(|line| !line.starts_with(char::is_whitespace))
+        .find_map(|line| {
+            let (name, value) = line.split_once('=')?;
+            let name = name.split(':').next()?.trim();
+            let value = value.trim();
+            let called = value.strip_prefix(constructor).or_else(|| {
+                value.split_once('.').and_then(|(_, rest)| rest.strip_prefix(constructor))
+            })?;
+            if called.starts_with('(') && is_identifier(name) {
+                Some(name.to_string())
+            } else {
+                None
+            }
+        })
+}
+
+fn is_identifier(name: &str) -> bool {
+    let mut chars = name.chars();
+    matches!(chars.next(), Some(c) if c.is_alphabetic() || c == '_')
+        && chars.all(|c| c.is_alphanumeric() || c == '_')
+}
+
+// Package holding the Django settings, read from `manage.py` or found by looking
+// for a directory with both `settings.py` and `wsgi.py`
+fn django_project(path: &Path) -> Option<String> {
+    if let Ok(manage) = fs::read_to_string(path.join("manage.py")) {
+        let settings = manage.lines()
+            .filter(|line| line.contains("DJANGO_SETTINGS_MODULE"))
+            .find_map(|line| {
+                line.split(['"', '\''])
+                    .map(str::trim)
+                    .find(|part| part.ends_with(".settings") || part.contains(".settings."))
+            });
+        if let Some(settings) = settings {
+            if let Some((project, _)) = settings.split_once(".settings") {
+                return Some(project.to_string());
+            }
+        }
+    }
+
+    let mut dirs = fs::read_dir(path).ok()?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|dir| dir.join("settings.py").is_file() && dir.join("wsgi.py").is_file())
+        .collect::<Vec<_>>();
+    dirs.sort();
+    dirs.first()?.file_name()?.to_str().map(String::from)
+}
+
+// Guesses the framework of the project from its Django layout or the imports of its modules
+pub fn detect_framework(path: &Path) -> Option<&'static str> {
+    if path.join("manage.py").exists() || django_project(path).is_some() {
+        return Some("django");
+    }
+
+    let sources = candidate_modules(path).iter()
+        .filter_map(|(_, file)| fs::read_to_string(file).ok())
+        .collect::<Vec<_>>();
+    for (module, framework) in [("fastapi", "fastapi"), ("flask", "flask"), ("django", "django")] {
+        if sources.iter().any(|source| imports(source, module)) {
+            return Some(framework);
+        }
+    }
+    None
+}
+
+// Infers the `module:callable` gunicorn should serve for a project of the given framework
+pub fn detect_app(path: &Path, framework: &str) -> Option<String> {
+    match framework {
+        "django" => {
+            if let Some(project) = django_project(path) {
+                if module_file(path, &format!("{}.wsgi", project)).is_some() {
+                    return Some(format!("{}.wsgi:application", project));
+                }
+            }
+            module_file(path, "wsgi").map(|_| String::from("wsgi:application"))
+        },
+        "flask" | "fastapi" => {
+            let constructor = if framework == "flask" { "Flask" } else { "FastAPI" };
+            let candidates = candidate_modules(path);
+            let sources = candidates.iter()
+                .filter_map(|(module, file)| fs::read_to_string(file).ok().map(|source| (module, source)))
+                .collect::<Vec<_>>();
+
+            // An application object, then an application factory, then the object
+            // conventionally exposed by `wsgi.py`/`asgi.py`
+            sources.iter()
+                .find_map(|(module, source)| assigned_from(source, constructor).map(|app| format!("{}:{}", module, app)))
+                .or_else(|| sources.iter().find_map(|(module, source)| {
+                    source.lines()
+                        .any(|line| line.starts_with("def create_app("))
+                        .then(|| format!("{}:create_app()", module))
+                }))
+                .or_else(|| sources.iter().find_map(|(module, source)| {
+                    if module.as_str() != "wsgi" && module.as_str() != "asgi" {
+                        return None;
+                    }
+                    ["app", "application"].iter()
+                        .find(|name| source.lines().any(|line| line.starts_with(&format!("{} =", name))))
+                        .map(|name| format!("{}:{}", module, name))
+                }))
+        },
+        _ => None,
+    }
+}
+
+// Validates an entry point given as `module:callable`
+pub fn parse_app(app: &str) -> Result<String, String> {
+    let (module, callable) = app.split_once(':')
+        .ok_or(format!("Invalid entry point '{}', expected module:callable", app))?;
+    let callable_name = callable.trim_end_matches("()");
+    if module.split('.').all(is_identifier) && is_identifier(callable_name) {
+        Ok(app.to_string())
+    } else {
+        Err(format!("Invalid entry point '{}', expected module:callable", app))
+    }
+}
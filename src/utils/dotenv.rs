@@ -0,0 +1,51 @@
+use std::fs;
+use std::path::Path;
+
+// Parses a `.env` style file into key/value pairs, in file order.
+// Blank lines and `#` comments are skipped, an optional `export ` prefix is
+// accepted and values wrapped in matching single or double quotes are unquoted.
+pub fn parse_env_file(path: &Path) -> Result<Vec<(String, String)>, String> {
+    let contents = fs::read_to_string(path)
+        .map_err(|e| format!("Failed to read env file {}: {}", path.display(), e))?;
+
+    let mut vars = Vec::new();
+    for (number, line) in contents.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let line = line.strip_prefix("export ").unwrap_or(line);
+
+        let (key, value) = line.split_once('=')
+            .ok_or_else(|| format!("Invalid line {} in env file {}: expected KEY=VALUE", number + 1, path.display()))?;
+
+        vars.push((key.trim().to_string(), unquote(value.trim()).to_string()));
+    }
+    Ok(vars)
+}
+
+// Splits a `KEY=VALUE` command line argument
+pub fn parse_assignment(assignment: &str) -> Result<(String, String), String> {
+    match assignment.split_once('=') {
+        Some((key, value)) if !key.is_empty() => Ok((key.to_string(), value.to_string())),
+        _ => Err(format!("Invalid variable '{}', expected KEY=VALUE", assignment)),
+    }
+}
+
+// Hides the value of an environment variable for display
+pub fn mask_value(value: &str) -> String {
+    if value.is_empty() {
+        String::new()
+    } else {
+        String::from("****")
+    }
+}
+
+fn unquote(value: &str) -> &str {
+    let bytes = value.as_bytes();
+    if bytes.len() >= 2 && (bytes[0] == b'"' || bytes[0] == b'\'') && bytes[0] == bytes[bytes.len() - 1] {
+        &value[1..value.len() - 1]
+    } else {
+        value
+    }
+}
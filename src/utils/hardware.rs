@@ -1,25 +1,44 @@
+use serde::Serialize;
 use sysinfo::{System, SystemExt, ProcessorExt};
+use crate::utils::output::Report;
+
+// CPU and memory usage of the host, shown by `hardware`
+#[derive(Debug, Serialize)]
+pub struct SystemUsage {
+    pub cpu_usage: f64, // Average over all processors, between 0 and 1
+    pub memory_usage: f64, // Between 0 and 1
+    pub total_memory: u64, // In KB
+    pub used_memory: u64, // In KB
+}
 
-pub fn monitor_system_info() {
-    let mut sys = System::new_all();
-    print_cpu_usage(&mut sys);
-    print_memory_info(&mut sys);
+impl Report for SystemUsage {
+    fn print_text(&self) {
+        let cpu_bar = generate_display_bar(self.cpu_usage, 20);
+        println!("{:<15} {:6.2} % |{}|", "CPU USAGE:", self.cpu_usage * 100.0, cpu_bar);
+
+        let memory_bar = generate_display_bar(self.memory_usage, 20);  // 20 units wide bar
+        println!("{:<15} {:6.2} % |{}|", "MEMORY USAGE:", self.memory_usage * 100.0, memory_bar);
+    }
 }
 
-fn print_memory_info(sys: &mut System) {
+pub fn monitor_system_info() -> SystemUsage {
+    let mut sys = System::new_all();
+    let cpu_usage = cpu_usage(&mut sys);
+
     sys.refresh_memory();
-    
     let total_memory = sys.total_memory();
     let used_memory = sys.used_memory();
-    let memory_usage = used_memory as f64 / total_memory as f64;
-    let memory_bar = generate_display_bar(memory_usage, 20);  // 20 units wide bar
-
-    println!("{:<15} {:6.2} % |{}|", "MEMORY USAGE:", memory_usage * 100.0, memory_bar);
+    SystemUsage {
+        cpu_usage,
+        memory_usage: used_memory as f64 / total_memory as f64,
+        total_memory,
+        used_memory,
+    }
 }
 
-fn print_cpu_usage(sys: &mut System) {
+fn cpu_usage(sys: &mut System) -> f64 {
     sys.refresh_cpu();
-    
+
     let processor_info = sys.processors();
 
     let mut total_cpu_usage = 0.0;
@@ -28,16 +47,13 @@ fn print_cpu_usage(sys: &mut System) {
         total_cpu_usage += processor.cpu_usage();
     }
 
-    let average_cpu_usage = total_cpu_usage as f64 / (processor_info.len() as f64 * 100.0);
-    let cpu_bar = generate_display_bar(average_cpu_usage, 20);
-
-    println!("{:<15} {:6.2} % |{}|", "CPU USAGE:", average_cpu_usage * 100.0, cpu_bar);
+    total_cpu_usage as f64 / (processor_info.len() as f64 * 100.0)
 }
 
 
 // Generate a textual display bar given a value between 0 and 1 and the bar width
 fn generate_display_bar(value: f64, bar_width: usize) -> String {
-    assert!(0.0 <= value && value <= 1.0);
+    assert!((0.0..=1.0).contains(&value));
 
     let filled = (value * bar_width as f64).round() as usize;
     let unfilled = bar_width - filled;
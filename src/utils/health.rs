@@ -0,0 +1,79 @@
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream, ToSocketAddrs};
+use std::thread;
+use std::time::{Duration, Instant};
+
+// Sends a plain `GET` request and returns the HTTP status code of the response
+pub fn check_http(bind: &str, port: u32, path: &str) -> Result<u16, String> {
+    // Servers bound to every interface are reached through the loopback
+    let host = if bind == "0.0.0.0" { "127.0.0.1" } else { bind };
+    let address = (host, port as u16).to_socket_addrs()
+        .map_err(|e| format!("Invalid address {}:{}: {}", host, port, e))?
+        .next()
+        .ok_or(format!("Invalid address {}:{}", host, port))?;
+
+    let mut stream = TcpStream::connect_timeout(&address, Duration::from_secs(2))
+        .map_err(|e| format!("Failed to connect to {}:{}: {}", host, port, e))?;
+    stream.set_read_timeout(Some(Duration::from_secs(5))).map_err(|e| e.to_string())?;
+
+    let request = format!("GET {} HTTP/1.0\r\nHost: {}:{}\r\nConnection: close\r\n\r\n", path, host, port);
+    stream.write_all(request.as_bytes())
+        .map_err(|e| format!("Failed to send health check: {}", e))?;
+
+    let mut response = [0; 64];
+    let read = stream.read(&mut response)
+        .map_err(|e| format!("Failed to read health check response: {}", e))?;
+    let status_line = String::from_utf8_lossy(&response[..read]);
+
+    status_line.split_whitespace()
+        .nth(1)
+        .and_then(|code| code.parse().ok())
+        .ok_or(format!("Invalid HTTP response from {}:{}", host, port))
+}
+
+// Polls the server until it answers with a non 5xx status or `timeout` expires
+pub fn wait_until_healthy(bind: &str, port: u32, path: &str, timeout: Duration) -> Result<(), String> {
+    let started = Instant::now();
+    loop {
+        let error = match check_http(bind, port, path) {
+            Ok(status) if status < 500 => return Ok(()),
+            Ok(status) => format!("responded with status {}", status),
+            Err(e) => e,
+        };
+
+        if started.elapsed() >= timeout {
+            return Err(format!("Health check on port {} failed after {}s: {}", port, timeout.as_secs(), error));
+        }
+        thread::sleep(Duration::from_millis(500));
+    }
+}
+
+// Polls until a TCP connection to the port succeeds, for services that don't speak HTTP
+pub fn wait_until_listening(bind: &str, port: u32, timeout: Duration) -> Result<(), String> {
+    let host = if bind == "0.0.0.0" { "127.0.0.1" } else { bind };
+    let address = (host, port as u16).to_socket_addrs()
+        .map_err(|e| format!("Invalid address {}:{}: {}", host, port, e))?
+        .next()
+        .ok_or(format!("Invalid address {}:{}", host, port))?;
+
+    let started = Instant::now();
+    loop {
+        let error = match TcpStream::connect_timeout(&address, Duration::from_secs(2)) {
+            Ok(_) => return Ok(()),
+            Err(e) => e,
+        };
+
+        if started.elapsed() >= timeout {
+            return Err(format!("Port {} not listening after {}s: {}", port, timeout.as_secs(), error));
+        }
+        thread::sleep(Duration::from_millis(500));
+    }
+}
+
+// Asks the OS for a port that is currently free on the loopback interface
+pub fn free_port() -> Result<u32, String> {
+    let listener = TcpListener::bind("127.0.0.1:0")
+        .map_err(|e| format!("Failed to find a free port: {}", e))?;
+    let port = listener.local_addr().map_err(|e| e.to_string())?.port();
+    Ok(port as u32)
+}
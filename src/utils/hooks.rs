@@ -0,0 +1,89 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::str::FromStr;
+use serde::{Serialize, Deserialize};
+use crate::server::server::Server;
+use crate::utils::build::{BuildStep, run_build_steps};
+
+// Seconds a hook may run when no timeout is given
+pub const DEFAULT_HOOK_TIMEOUT: u64 = 60;
+
+// Points of a server's lifecycle where a hook can run
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HookKind {
+    PreStart,
+    PostStart,
+    PreStop,
+    PostStop,
+    PreUpdate,
+    PostUpdate,
+}
+
+impl HookKind {
+    // A failing `pre_*` hook aborts the operation, `post_*` failures are only reported
+    pub fn is_pre(self) -> bool {
+        matches!(self, HookKind::PreStart | HookKind::PreStop | HookKind::PreUpdate)
+    }
+}
+
+impl fmt::Display for HookKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            HookKind::PreStart => write!(f, "pre_start"),
+            HookKind::PostStart => write!(f, "post_start"),
+            HookKind::PreStop => write!(f, "pre_stop"),
+            HookKind::PostStop => write!(f, "post_stop"),
+            HookKind::PreUpdate => write!(f, "pre_update"),
+            HookKind::PostUpdate => write!(f, "post_update"),
+        }
+    }
+}
+
+impl FromStr for HookKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "pre_start" => Ok(HookKind::PreStart),
+            "post_start" => Ok(HookKind::PostStart),
+            "pre_stop" => Ok(HookKind::PreStop),
+            "post_stop" => Ok(HookKind::PostStop),
+            "pre_update" => Ok(HookKind::PreUpdate),
+            "post_update" => Ok(HookKind::PostUpdate),
+            _ => Err(format!("Unknown hook '{}', expected one of pre_start, post_start, pre_stop, post_stop, pre_update, post_update", s)),
+        }
+    }
+}
+
+// Shell command run at a point of the server's lifecycle
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Hook {
+    pub command: String, // Shell command, run in the server directory with the server's environment
+    pub timeout: u64, // Seconds before the hook is killed
+}
+
+// Runs the hook of the given kind if the server defines one, appending its output to
+// `{log path}/{server name}-hooks.log`
+pub fn run_hook(server: &Server, kind: HookKind) -> Result<(), String> {
+    let hook = match server.hooks.get(&kind) {
+        Some(hook) => hook,
+        None => return Ok(()),
+    };
+
+    let mut env = BTreeMap::new();
+    env.insert(String::from("GSM_SERVER"), server.name.clone());
+    env.insert(String::from("GSM_HOOK"), kind.to_string());
+    let step = BuildStep {
+        command: hook.command.clone(),
+        dir: None,
+        env,
+        timeout: Some(hook.timeout),
+        patterns: Vec::new(),
+    };
+
+    let log_file = server.original_dir.join(&server.log_path).join(format!("{}-hooks.log", server.name));
+    run_build_steps(server, &[step], None, &log_file)
+        .map(|_| ())
+        .map_err(|e| format!("{} hook of [{}] failed: {}, see {} for details.", kind, server.name, e, log_file.display()))
+}
@@ -1,2 +1,10 @@
 pub mod hardware;
-pub mod build;
\ No newline at end of file
+pub mod build;
+pub mod dotenv;
+pub mod venv;
+pub mod deps;
+pub mod health;
+pub mod detect;
+pub mod hooks;
+pub mod output;
+pub mod state;
@@ -0,0 +1,105 @@
+use std::fmt;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use serde::Serialize;
+
+// Set once a command reports an error, so a command run from the shell exits with a failure status
+static FAILED: AtomicBool = AtomicBool::new(false);
+
+pub fn failed() -> bool {
+    FAILED.load(Ordering::Relaxed)
+}
+
+pub fn set_failed() {
+    FAILED.store(true, Ordering::Relaxed);
+}
+
+// How command results are printed, set with `--output` or `set output` in the prompt
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum OutputFormat {
+    #[default]
+    Text,
+    Json,
+}
+
+impl fmt::Display for OutputFormat {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            OutputFormat::Text => write!(f, "text"),
+            OutputFormat::Json => write!(f, "json"),
+        }
+    }
+}
+
+impl FromStr for OutputFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(OutputFormat::Text),
+            "json" => Ok(OutputFormat::Json),
+            _ => Err(format!("Unknown output format '{}', expected text or json", s)),
+        }
+    }
+}
+
+// Data returned by a command, printed for people or serialized for scripts
+pub trait Report: Serialize {
+    fn print_text(&self);
+}
+
+impl OutputFormat {
+    pub fn print<T: Report>(self, report: &T) {
+        match self {
+            OutputFormat::Text => report.print_text(),
+            OutputFormat::Json => match serde_json::to_string_pretty(report) {
+                Ok(json) => println!("{}", json),
+                Err(e) => eprintln!("[ERROR] Failed to serialize output: {}", e),
+            },
+        }
+    }
+
+    // Reports why a command reading servers failed, an error message on stderr as text,
+    // `{"error": ...}` as json so scripts reading stdout still get an object
+    pub fn error<E: fmt::Display>(self, e: E) {
+        set_failed();
+        match self {
+            OutputFormat::Text => eprintln!("[ERROR] {}", e),
+            OutputFormat::Json => println!("{}", serde_json::json!({ "error": e.to_string() })),
+        }
+    }
+}
+
+// Outcome of a command that changes servers instead of reading them
+#[derive(Debug, Serialize)]
+pub struct Outcome {
+    pub command: String,
+    pub name: Option<String>,
+    pub ok: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl Report for Outcome {
+    // Progress and success messages are already on stderr, only failures are left to report
+    fn print_text(&self) {
+        if let Some(error) = &self.error {
+            eprintln!("[ERROR] {}", error);
+        }
+    }
+}
+
+impl OutputFormat {
+    // Reports how a changing command went, an error message on stderr as text, an `Outcome` as json
+    pub fn outcome<E: fmt::Display>(self, command: &str, name: Option<&str>, result: Result<(), E>) {
+        if result.is_err() {
+            set_failed();
+        }
+        self.print(&Outcome {
+            command: command.to_string(),
+            name: name.map(|name| name.to_string()),
+            ok: result.is_ok(),
+            error: result.err().map(|e| e.to_string()),
+        });
+    }
+}
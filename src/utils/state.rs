@@ -0,0 +1,201 @@
+use std::env;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::os::unix::io::AsRawFd;
+use std::path::{self, Path, PathBuf};
+use std::sync::{Mutex, OnceLock};
+use nix::errno::Errno;
+use nix::fcntl::{flock, FlockArg};
+use crate::error::GsmError;
+
+// Environment variable overriding the default state directory
+pub const STATE_DIR_ENV: &str = "GSM_HOME";
+
+// Directory older versions kept their files in, relative to the working directory
+const LEGACY_DIR: &str = "backups";
+
+// Files of the legacy directory with their name in the state directory
+const LEGACY_FILES: [(&str, &str); 2] = [
+    ("servers_backup.json", STATE_FILE),
+    ("history.txt", HISTORY_FILE),
+];
+
+const STATE_FILE: &str = "servers.json";
+const HISTORY_FILE: &str = "history.txt";
+const PIDS_DIR: &str = "pids";
+const LOGS_DIR: &str = "logs";
+const WORKSPACE_DIR: &str = "workspace";
+
+static STATE_DIR: OnceLock<PathBuf> = OnceLock::new();
+
+// Command being run, recorded with the snapshots it triggers
+static COMMAND: Mutex<String> = Mutex::new(String::new());
+
+// State directory from `--state-dir`, then `GSM_HOME`, then `$XDG_STATE_HOME/GServerManager`
+// or `~/.local/state/GServerManager`. It's made absolute since servers run from their own directory.
+pub fn resolve(flag: Option<&Path>) -> PathBuf {
+    let non_empty = |var: &str| env::var_os(var).filter(|value| !value.is_empty()).map(PathBuf::from);
+    let dir = match flag {
+        Some(dir) => dir.to_path_buf(),
+        None => non_empty(STATE_DIR_ENV)
+            .or_else(|| non_empty("XDG_STATE_HOME").map(|state_home| state_home.join("GServerManager")))
+            .or_else(|| non_empty("HOME").map(|home| home.join(".local").join("state").join("GServerManager")))
+            .unwrap_or_else(|| PathBuf::from("backups")),
+    };
+    path::absolute(&dir).unwrap_or(dir)
+}
+
+// Sets the state directory for the rest of the process and creates it. With `migrate_legacy`
+// the files of the old `backups/` directory are moved into it
+pub fn init(flag: Option<&Path>, migrate_legacy: bool) -> Result<&'static Path, GsmError> {
+    let dir = resolve(flag);
+    fs::create_dir_all(dir.join(PIDS_DIR))
+        .and_then(|_| fs::create_dir_all(dir.join(LOGS_DIR)))
+        .map_err(|e| GsmError::Backup(format!("Failed to create state directory {}: {}", dir.display(), e)))?;
+
+    if migrate_legacy {
+        migrate(Path::new(LEGACY_DIR), &dir)?;
+    }
+    Ok(STATE_DIR.get_or_init(|| dir))
+}
+
+// Moves the files of an old `backups/` directory into the state directory,
+// unless it already has its own
+pub fn migrate(legacy_dir: &Path, dir: &Path) -> Result<(), GsmError> {
+    for (legacy, file) in LEGACY_FILES {
+        let legacy = legacy_dir.join(legacy);
+        let target = dir.join(file);
+        if legacy.is_file() && !target.exists() {
+            move_file(&legacy, &target)
+                .map_err(|e| GsmError::Backup(format!("Failed to move {} to {}: {}", legacy.display(), target.display(), e)))?;
+            eprintln!("[INFO] Moved {} to {}", legacy.display(), target.display());
+        }
+    }
+    Ok(())
+}
+
+// State directory set by `init`, resolved from the environment if it wasn't called
+pub fn state_dir() -> &'static Path {
+    STATE_DIR.get_or_init(|| resolve(None))
+}
+
+pub fn set_command(command: &str) {
+    if let Ok(mut current) = COMMAND.lock() {
+        *current = command.to_string();
+    }
+}
+
+pub fn command() -> String {
+    COMMAND.lock().map(|command| command.clone()).unwrap_or_default()
+}
+
+// Saved servers
+pub fn state_file() -> PathBuf {
+    state_dir().join(STATE_FILE)
+}
+
+// Commands typed in the prompt
+pub fn history_file() -> PathBuf {
+    state_dir().join(HISTORY_FILE)
+}
+
+// Pid files of the gunicorn masters
+pub fn pids_dir() -> PathBuf {
+    state_dir().join(PIDS_DIR)
+}
+
+// Default log directory of new servers
+pub fn logs_dir() -> PathBuf {
+    state_dir().join(LOGS_DIR)
+}
+
+// Directory servers added with `add --git` are cloned into
+pub fn workspace_dir() -> PathBuf {
+    state_dir().join(WORKSPACE_DIR)
+}
+
+// Exclusive advisory lock on a file next to the state file, held by the instance writing it
+// and released when dropped
+#[derive(Debug)]
+pub struct StateLock {
+    path: PathBuf,
+    _file: File,
+}
+
+impl StateLock {
+    // State file the lock is held for
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+// Claims `path` for writing. Fails right away while another instance holds it, the pid
+// written into the lock file names that instance in the error.
+pub fn claim(path: &Path) -> Result<StateLock, GsmError> {
+    let lock_path = with_suffix(path, "lock");
+    let mut file = OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .read(true)
+        .write(true)
+        .open(&lock_path)
+        .map_err(|e| GsmError::Backup(format!("Failed to open lock file {}: {}", lock_path.display(), e)))?;
+
+    match flock(file.as_raw_fd(), FlockArg::LockExclusiveNonblock) {
+        Ok(()) => (),
+        Err(nix::Error::Sys(Errno::EAGAIN)) => {
+            let holder = fs::read_to_string(&lock_path).unwrap_or_default();
+            let holder = match holder.trim() {
+                "" => String::new(),
+                pid => format!(" (pid {})", pid),
+            };
+            return Err(GsmError::InvalidState(format!(
+                "{} is used by another GServerManager instance{}, close it or use another --state-dir",
+                path.display(), holder
+            )));
+        },
+        Err(e) => return Err(GsmError::Backup(format!("Failed to lock {}: {}", lock_path.display(), e))),
+    }
+
+    file.set_len(0)
+        .and_then(|_| file.write_all(std::process::id().to_string().as_bytes()))
+        .map_err(|e| GsmError::Backup(format!("Failed to write lock file {}: {}", lock_path.display(), e)))?;
+    Ok(StateLock { path: path.to_path_buf(), _file: file })
+}
+
+// Writes a temp file, syncs it and renames it over `path`, so a crash leaves
+// either the old or the new contents
+pub fn write_atomic(path: &Path, contents: &[u8]) -> io::Result<()> {
+    let tmp = with_suffix(path, "tmp");
+    let mut file = File::create(&tmp)?;
+    file.write_all(contents)?;
+    file.sync_all()?;
+    fs::rename(&tmp, path)?;
+
+    // The rename is only durable once the directory is synced
+    match path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+        Some(dir) => File::open(dir)?.sync_all(),
+        None => Ok(()),
+    }
+}
+
+// Copy of the state file as it was before the last write, restored if the state file is corrupt
+pub fn last_good(path: &Path) -> PathBuf {
+    with_suffix(path, "bak")
+}
+
+// `servers.json` -> `servers.json.{suffix}`
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(".");
+    name.push(suffix);
+    path.with_file_name(name)
+}
+
+// `rename` doesn't work across filesystems, copy the file there instead
+fn move_file(from: &Path, to: &Path) -> io::Result<()> {
+    fs::rename(from, to).or_else(|_| {
+        fs::copy(from, to)?;
+        fs::remove_file(from)
+    })
+}
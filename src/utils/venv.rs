@@ -0,0 +1,25 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// Folder names checked inside a server directory when no virtualenv is given
+const VENV_DIRS: [&str; 3] = [".venv", "venv", "env"];
+
+// Looks for a virtualenv inside the server directory
+pub fn detect_venv(path: &Path) -> Option<PathBuf> {
+    VENV_DIRS.iter()
+        .map(|dir| path.join(dir))
+        .find(|venv| venv.join("bin").join("python").exists())
+}
+
+// Checks that the virtualenv provides gunicorn and returns its absolute path,
+// so it stays valid when commands run from the server directory
+pub fn validate_venv(venv: &Path) -> Result<PathBuf, String> {
+    let venv = fs::canonicalize(venv)
+        .map_err(|e| format!("Invalid virtualenv {}: {}", venv.display(), e))?;
+
+    if venv.join("bin").join("gunicorn").exists() {
+        Ok(venv)
+    } else {
+        Err(format!("gunicorn not found in virtualenv {}, install it with `{}/bin/pip install gunicorn`", venv.display(), venv.display()))
+    }
+}
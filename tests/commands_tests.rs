@@ -0,0 +1,101 @@
+use structopt::clap::Shell;
+use GServerManager::commands::completion::{complete, completion_script, Completion};
+use GServerManager::commands::man::man_page;
+use GServerManager::commands::command::{Command, HooksCommand};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn words(words: &[&str]) -> Completion {
+        Completion::Words(words.iter().map(|word| word.to_string()).collect())
+    }
+
+    #[test]
+    fn test_complete_prompt_line() {
+        let names = vec![String::from("api-1"), String::from("api-2"), String::from("worker")];
+
+        assert_eq!(complete(&[], "re", &names), words(&["redis", "releases", "reload", "remove", "restart"]));
+        assert_eq!(complete(&["releases"], "", &names), words(&["enable", "list"]));
+        assert_eq!(complete(&["start", "--name"], "api", &names), words(&["api-1", "api-2"]));
+        assert_eq!(complete(&["stop", "-n"], "", &names), words(&["api-1", "api-2", "worker"]));
+        assert_eq!(complete(&["add", "--path"], "tests/", &names), Completion::Path);
+        assert_eq!(complete(&["set", "output"], "j", &names), words(&["json"]));
+        assert_eq!(complete(&["start"], "--pa", &names), words(&["--parallel"]));
+        assert_eq!(complete(&["edit", "--depends-on"], "w", &names), words(&["worker"]));
+
+        // A value already given doesn't change what comes next
+        assert_eq!(complete(&["start", "--name", "api-1"], "--a", &names), words(&["--all"]));
+    }
+
+    #[test]
+    fn test_completion_scripts_complete_server_names() {
+        let bash = completion_script(Shell::Bash);
+        assert!(bash.contains("--name|--depends-on|--drop-depends-on|-n)"));
+        assert!(bash.contains("complete -F _GServerManager_servers"));
+
+        let zsh = completion_script(Shell::Zsh);
+        assert!(zsh.contains("'--name=[]: :_GServerManager_servers'"));
+        assert!(zsh.contains("'--name=[Server name, or a glob matching several]: :_GServerManager_servers'"));
+        assert!(zsh.find("_GServerManager_servers() {") < zsh.rfind("_GServerManager \"$@\""));
+
+        let fish = completion_script(Shell::Fish);
+        assert!(fish.contains("-l depends-on -r -f -a \"(GServerManager completions --servers 2>/dev/null)\""));
+    }
+
+    #[test]
+    fn test_man_page() {
+        let page = man_page();
+        assert!(page.starts_with(".TH GSERVERMANAGER 1"));
+        assert!(page.contains(".SS \"releases enable\""));
+        assert!(!page.contains(".SS \"help\""));
+
+        // Descriptions come from the doc comments, hidden flags are left out
+        assert!(page.contains("Print a completion script for bash, zsh or fish"));
+        assert!(page.contains("Branch, tag or commit to deploy"));
+        assert!(!page.contains("\\-\\-servers"));
+    }
+
+    #[test]
+    fn test_prompt_line_keeps_quoted_values() {
+        let command = Command::from_prompt("hooks set -n api post_update -c \"make install\" -t 30").unwrap();
+        match command {
+            Command::Hooks(HooksCommand::Set { name, command, timeout, .. }) => {
+                assert_eq!(name, "api");
+                assert_eq!(command, "make install");
+                assert_eq!(timeout, Some(30));
+            },
+            other => panic!("Unexpected command {:?}", other),
+        }
+        assert!(Command::from_prompt("hooks set -n api post_update -c \"make").is_err());
+    }
+
+    #[test]
+    fn test_edit_clears_optional_settings() {
+        match Command::from_prompt("edit -n api --no-venv --no-app --no-env-file").unwrap() {
+            Command::Edit { venv, no_venv, no_app, no_env_file, .. } => {
+                assert!(venv.is_none());
+                assert!(no_venv && no_app && no_env_file);
+            },
+            other => panic!("Unexpected command {:?}", other),
+        }
+        assert!(Command::from_prompt("edit -n api --venv .venv --no-venv").is_err());
+    }
+
+    #[test]
+    fn test_failing_read_command_prints_json_error() {
+        let dir = std::env::temp_dir().join("gsm_test_json_error");
+        let _ = std::fs::remove_dir_all(&dir);
+
+        let output = std::process::Command::new(env!("CARGO_BIN_EXE_GServerManager"))
+            .arg("--state-dir").arg(&dir)
+            .args(["--output", "json", "git_status", "-n", "missing"])
+            .output()
+            .unwrap();
+        assert!(!output.status.success());
+        let error: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+        assert!(error["error"].as_str().unwrap().contains("missing"));
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
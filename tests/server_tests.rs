@@ -1,7 +1,389 @@
 use GServerManager::server::server::Server;
-use std::path::PathBuf;
+use GServerManager::server::releases::{enable_releases, list_releases};
+use GServerManager::server::django::{manage, run_deploy_steps};
+use GServerManager::server::gunicorn::get_gunicorn_command;
+use GServerManager::utils::hooks::{Hook, HookKind, run_hook};
+use GServerManager::utils::venv::{detect_venv, validate_venv};
+use GServerManager::utils::state;
+use GServerManager::error::GsmError;
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+use std::process::Command;
 
 #[cfg(test)]
 mod tests {
-    // Need updated tests
+    use super::*;
+
+    const APP: &str = "from flask import Flask\n\napp = Flask(__name__)\n";
+
+    fn test_server(name: &str, dir: &str) -> Server {
+        let path = PathBuf::from("tests/test-servers").join(dir);
+        Server::new(name, path, 8000)
+    }
+
+    #[test]
+    fn test_is_valid() {
+        assert!(test_server("server-1", "server-1").is_valid());
+        assert!(test_server("server-2", "server-2").is_valid());
+        assert!(test_server("server-3", "server-3").is_valid());
+        assert!(!test_server("missing", "missing").is_valid());
+
+        let mut server = test_server("missing", "missing");
+        server.app = Some(String::from("main:app"));
+        assert!(server.is_valid());
+        assert_eq!(test_server("server-3", "server-3").app_string().unwrap(), "server:app");
+    }
+
+    #[test]
+    fn test_keep_running_address() {
+        let mut current = test_server("address-test", "server-1");
+        current.running = true;
+        let mut edited = Server { port: 8001, ..current.clone() };
+
+        edited.keep_running_address(&current);
+        assert_eq!(edited.running_address, Some((current.bind.clone(), 8000)));
+
+        // Changing it back to the served address needs no restart anymore
+        let mut reverted = Server { port: 8000, ..edited.clone() };
+        reverted.keep_running_address(&edited);
+        assert_eq!(reverted.running_address, None);
+
+        current.running = false;
+        edited.keep_running_address(&current);
+        assert_eq!(edited.running_address, None);
+    }
+
+    #[test]
+    fn test_gunicorn_command_quotes_factory_apps() {
+        let server = test_server("factory's app", "server-1");
+        let command = get_gunicorn_command(&server, "flask", "main:create_app()", "/tmp/gsm logs").unwrap();
+        assert!(command.ends_with(" 'main:create_app()'"));
+
+        let status = Command::new("sh").arg("-n").arg("-c").arg(&command).status().unwrap();
+        assert!(status.success());
+    }
+
+    #[test]
+    fn test_environment_overrides_env_file() {
+        let env_file = std::env::temp_dir().join("gsm_test_environment.env");
+        std::fs::write(&env_file, "# comment\nexport DEBUG=\"1\"\nSECRET='abc'\n\nPORT=1\n").unwrap();
+
+        let mut server = test_server("server-1", "server-1");
+        server.env_file = Some(env_file.clone());
+        server.env.insert(String::from("PORT"), String::from("2"));
+
+        let vars = server.environment().unwrap();
+        assert_eq!(vars.get("DEBUG").map(String::as_str), Some("1"));
+        assert_eq!(vars.get("SECRET").map(String::as_str), Some("abc"));
+        assert_eq!(vars.get("PORT").map(String::as_str), Some("2"));
+
+        std::fs::remove_file(env_file).unwrap();
+    }
+
+    #[test]
+    fn test_venv_detection() {
+        let dir = std::env::temp_dir().join("gsm_test_venv_detection");
+        let bin = dir.join(".venv").join("bin");
+        std::fs::create_dir_all(&bin).unwrap();
+        std::fs::write(bin.join("python"), "").unwrap();
+
+        let venv = detect_venv(&dir).unwrap();
+        assert_eq!(venv, dir.join(".venv"));
+        assert!(validate_venv(&venv).is_err());
+
+        std::fs::write(bin.join("gunicorn"), "").unwrap();
+        let venv = validate_venv(&venv).unwrap();
+
+        let mut server = test_server("server-1", "server-1");
+        assert_eq!(server.venv_bin("gunicorn"), "gunicorn");
+        server.venv = Some(venv.clone());
+        assert_eq!(server.venv_bin("gunicorn"), venv.join("bin/gunicorn").display().to_string());
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    fn git(dir: &Path, args: &[&str]) -> String {
+        let output = Command::new("git")
+            .args(["-c", "user.name=test", "-c", "user.email=test@example.com"])
+            .args(args)
+            .current_dir(dir)
+            .output()
+            .unwrap();
+        assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+        String::from_utf8_lossy(&output.stdout).trim().to_owned()
+    }
+
+    #[test]
+    fn test_deploy_and_rollback() {
+        let dir = std::env::temp_dir().join("gsm_test_deploy");
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        git(&dir, &["init", "-q"]);
+        std::fs::write(dir.join("main.py"), format!("{}# v1\n", APP)).unwrap();
+        git(&dir, &["add", "-A"]);
+        git(&dir, &["commit", "-qm", "v1"]);
+        git(&dir, &["tag", "v1"]);
+        let v1 = git(&dir, &["rev-parse", "HEAD"]);
+        std::fs::write(dir.join("main.py"), format!("{}# v2\n", APP)).unwrap();
+        git(&dir, &["commit", "-qam", "v2"]);
+        let v2 = git(&dir, &["rev-parse", "HEAD"]);
+
+        let mut server = test_server("deploy-test", "server-1");
+        server.path = dir.clone();
+        server.log_path = dir.clone();
+        server.github = true;
+
+        server.deploy("v1").unwrap();
+        assert_eq!(git(&dir, &["rev-parse", "HEAD"]), v1);
+        assert_eq!(server.deployed_commit.as_deref(), Some(v1.as_str()));
+        assert_eq!(server.previous_commit.as_deref(), Some(v2.as_str()));
+
+        server.rollback().unwrap();
+        assert_eq!(git(&dir, &["rev-parse", "HEAD"]), v2);
+        assert_eq!(server.previous_commit.as_deref(), Some(v1.as_str()));
+
+        assert!(matches!(server.deploy("missing-ref"), Err(GsmError::InvalidArgument(_))));
+        assert_eq!(git(&dir, &["rev-parse", "HEAD"]), v2);
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_update_after_deploying_a_tag() {
+        let dir = std::env::temp_dir().join("gsm_test_update_detached");
+        let _ = std::fs::remove_dir_all(&dir);
+        let origin = dir.join("origin");
+        std::fs::create_dir_all(&origin).unwrap();
+        git(&origin, &["init", "-q", "-b", "main"]);
+        std::fs::write(origin.join("main.py"), format!("{}# v1\n", APP)).unwrap();
+        git(&origin, &["add", "-A"]);
+        git(&origin, &["commit", "-qm", "v1"]);
+        git(&origin, &["tag", "v1"]);
+        std::fs::write(origin.join("main.py"), format!("{}# v2\n", APP)).unwrap();
+        git(&origin, &["commit", "-qam", "v2"]);
+        let app = dir.join("app");
+        git(&dir, &["clone", "-q", origin.to_str().unwrap(), "app"]);
+
+        let mut server = test_server("detached-test", "server-1");
+        server.path = app.clone();
+        server.log_path = app.clone();
+        server.github = true;
+
+        // Rolling back to a tag leaves a detached HEAD, update still follows main
+        server.deploy("v1").unwrap();
+        assert_eq!(server.tracked_branch().unwrap().as_deref(), Some("main"));
+        std::fs::write(origin.join("main.py"), format!("{}# v3\n", APP)).unwrap();
+        git(&origin, &["commit", "-qam", "v3"]);
+        assert!(server.has_pending_commits().unwrap());
+        server.update().unwrap();
+        assert_eq!(git(&app, &["rev-parse", "HEAD"]), git(&origin, &["rev-parse", "HEAD"]));
+        assert_eq!(git(&app, &["symbolic-ref", "--short", "HEAD"]), "main");
+
+        // Local commits are never dropped to follow a diverged origin
+        std::fs::write(app.join("local.txt"), "local\n").unwrap();
+        git(&app, &["add", "local.txt"]);
+        git(&app, &["commit", "-qm", "local"]);
+        let local = git(&app, &["rev-parse", "HEAD"]);
+        std::fs::write(origin.join("main.py"), format!("{}# v4\n", APP)).unwrap();
+        git(&origin, &["commit", "-qam", "v4"]);
+        assert!(matches!(server.update(), Err(GsmError::Git(_))));
+        assert_eq!(git(&app, &["rev-parse", "HEAD"]), local);
+        server.deploy("v1").unwrap();
+        assert!(matches!(server.update(), Err(GsmError::Git(_))));
+        assert_eq!(git(&app, &["rev-parse", "main"]), local);
+        git(&app, &["checkout", "-q", "main"]);
+
+        // A directory inside the repository must not move the repository
+        std::fs::create_dir_all(app.join("nested")).unwrap();
+        std::fs::write(app.join("nested").join("main.py"), APP).unwrap();
+        let mut nested = test_server("nested-test", "server-1");
+        nested.path = app.join("nested");
+        nested.github = true;
+        assert!(matches!(nested.deploy("v1"), Err(GsmError::InvalidState(_))));
+        assert_eq!(git(&app, &["symbolic-ref", "--short", "HEAD"]), "main");
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_enable_releases() {
+        let dir = std::env::temp_dir().join("gsm_test_releases");
+        let _ = std::fs::remove_dir_all(&dir);
+        let source = dir.join("source");
+        std::fs::create_dir_all(&source).unwrap();
+        git(&source, &["init", "-q"]);
+        std::fs::write(source.join("main.py"), format!("{}# v1\n", APP)).unwrap();
+        git(&source, &["add", "-A"]);
+        git(&source, &["commit", "-qm", "v1"]);
+        let head = git(&source, &["rev-parse", "HEAD"]);
+
+        let mut server = test_server("releases-test", "server-1");
+        server.path = source.clone();
+        server.github = true;
+
+        let root = dir.join("app");
+        enable_releases(&mut server, &root, 3).unwrap();
+        assert_eq!(server.path, root.join("current"));
+        assert_eq!(server.pid_file(), state::pids_dir().join("releases-test.pid"));
+        assert!(server.is_valid());
+
+        let releases = list_releases(&root).unwrap();
+        assert_eq!(releases.len(), 1);
+        assert_eq!(std::fs::read_link(root.join("current")).unwrap(), releases[0]);
+        assert_eq!(git(&releases[0], &["rev-parse", "HEAD"]), head);
+
+        // A release with its own virtualenv runs in it, the others in the server's one
+        assert_eq!(server.active_venv(), None);
+        std::fs::create_dir_all(releases[0].join(".venv/bin")).unwrap();
+        assert_eq!(server.active_venv(), Some(root.join("current/.venv")));
+        assert_eq!(server.venv_bin("gunicorn"), root.join("current/.venv/bin/gunicorn").display().to_string());
+
+        assert!(enable_releases(&mut server, &root, 3).is_err());
+        assert!(server.rollback().is_err());
+
+        // A rollback that fails to reload runs the update hooks and leaves `current` on the live release
+        let older = root.join("releases").join("0-older");
+        git(&dir, &["clone", "-q", root.join("repo").to_str().unwrap(), older.to_str().unwrap()]);
+        server.log_path = dir.clone();
+        let marker = dir.join("pre_update");
+        server.hooks.insert(HookKind::PreUpdate, Hook { command: format!("touch {}", marker.display()), timeout: 5 });
+        server.running = true;
+        assert!(server.rollback().is_err());
+        assert!(marker.exists());
+        assert_eq!(std::fs::read_link(root.join("current")).unwrap(), releases[0]);
+        assert_eq!(server.deployed_commit, Some(head));
+        server.running = false;
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_has_pending_commits() {
+        let dir = std::env::temp_dir().join("gsm_test_pending_commits");
+        let _ = std::fs::remove_dir_all(&dir);
+        let origin = dir.join("origin");
+        std::fs::create_dir_all(&origin).unwrap();
+        git(&origin, &["init", "-q"]);
+        std::fs::write(origin.join("main.py"), "v1").unwrap();
+        git(&origin, &["add", "-A"]);
+        git(&origin, &["commit", "-qm", "v1"]);
+        git(&dir, &["clone", "-q", "origin", "clone"]);
+
+        let mut server = test_server("pending-test", "server-1");
+        server.path = dir.join("clone");
+        server.github = true;
+        assert!(!server.has_pending_commits().unwrap());
+
+        std::fs::write(origin.join("main.py"), "v2").unwrap();
+        git(&origin, &["commit", "-qam", "v2"]);
+        assert!(server.has_pending_commits().unwrap());
+
+        git(&server.path, &["pull", "-q"]);
+        assert!(!server.has_pending_commits().unwrap());
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_git_status() {
+        let dir = std::env::temp_dir().join("gsm_test_git_status");
+        let _ = std::fs::remove_dir_all(&dir);
+        let origin = dir.join("origin");
+        std::fs::create_dir_all(&origin).unwrap();
+        git(&origin, &["init", "-q", "-b", "main"]);
+        std::fs::write(origin.join("main.py"), "v1").unwrap();
+        git(&origin, &["add", "-A"]);
+        git(&origin, &["commit", "-qm", "first"]);
+        git(&dir, &["clone", "-q", "origin", "clone"]);
+        std::fs::write(origin.join("requirements.txt"), "flask").unwrap();
+        git(&origin, &["add", "-A"]);
+        git(&origin, &["commit", "-qm", "second"]);
+
+        let mut server = test_server("status-test", "server-1");
+        server.path = dir.join("clone");
+        assert!(server.git_status().is_err());
+        server.github = true;
+        std::fs::write(server.path.join("local.py"), "local").unwrap();
+        git(&server.path, &["add", "-A"]);
+        git(&server.path, &["commit", "-qm", "local"]);
+        std::fs::write(server.path.join("main.py"), "local").unwrap();
+
+        let status = server.git_status().unwrap();
+        assert_eq!(status.branch.as_deref(), Some("main"));
+        assert_eq!(status.subject, "local");
+        assert_eq!(status.upstream.as_deref(), Some("origin/main"));
+        assert_eq!((status.ahead, status.behind), (1, 1));
+        assert_eq!(status.dirty, vec![" M main.py"]);
+        // Local commits are not part of what an update brings in
+        assert_eq!(status.incoming, vec!["requirements.txt"]);
+        assert_eq!(server.short_commit().unwrap(), &status.head[..7]);
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_django_deploy_steps() {
+        let dir = std::env::temp_dir().join("gsm_test_django");
+        let _ = std::fs::remove_dir_all(&dir);
+        let bin = dir.join("venv/bin");
+        std::fs::create_dir_all(&bin).unwrap();
+        std::fs::write(dir.join("manage.py"), "").unwrap();
+        // Records its arguments and fails on `collectstatic` once `fail` exists
+        let python = bin.join("python");
+        std::fs::write(&python, "#!/bin/sh\necho \"$@\" >> calls\n[ \"$2\" = collectstatic ] && [ -f fail ] && exit 1\nexit 0\n").unwrap();
+        std::fs::set_permissions(&python, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+        let mut server = test_server("django-test", "server-1");
+        server.path = dir.clone();
+        server.log_path = dir.clone();
+        server.venv = Some(dir.join("venv"));
+        assert!(manage(&server, &[String::from("check")]).is_err());
+        assert!(run_deploy_steps(&server).is_ok());
+        assert!(!dir.join("calls").exists());
+
+        server.framework = String::from("django");
+        manage(&server, &[String::from("check")]).unwrap();
+        run_deploy_steps(&server).unwrap();
+        let calls = std::fs::read_to_string(dir.join("calls")).unwrap();
+        assert_eq!(calls, "manage.py check\nmanage.py migrate --noinput\nmanage.py collectstatic --noinput\n");
+
+        std::fs::write(dir.join("fail"), "").unwrap();
+        assert!(run_deploy_steps(&server).is_err());
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_lifecycle_hooks() {
+        let dir = std::env::temp_dir().join("gsm_test_hooks");
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let mut server = test_server("hooks-test", "server-1");
+        server.path = dir.clone();
+        server.log_path = dir.clone();
+        server.env.insert(String::from("GREETING"), String::from("hello"));
+        let hook = |command: &str, timeout: u64| Hook { command: command.to_string(), timeout };
+
+        server.hooks.insert(HookKind::PostStart, hook("echo \"$GREETING $GSM_HOOK\" > out", 5));
+        run_hook(&server, HookKind::PostStart).unwrap();
+        assert_eq!(std::fs::read_to_string(dir.join("out")).unwrap(), "hello post_start\n");
+        assert!(run_hook(&server, HookKind::PreStart).is_ok());
+
+        server.hooks.insert(HookKind::PreUpdate, hook("sleep 5", 1));
+        assert!(run_hook(&server, HookKind::PreUpdate).unwrap_err().contains("timed out"));
+        assert!(matches!(server.deploy("main"), Err(GsmError::Hook(_))));
+
+        // A failing pre_stop hook leaves the server running
+        server.running = true;
+        server.hooks.insert(HookKind::PreStop, hook("exit 1", 5));
+        assert!(matches!(server.stop(), Err(GsmError::Hook(_))));
+        assert!(server.running);
+        assert!(std::fs::read_to_string(dir.join("hooks-test-hooks.log")).unwrap().contains("$ cd"));
+
+        assert_eq!("post_update".parse::<HookKind>().unwrap(), HookKind::PostUpdate);
+        assert!("on_start".parse::<HookKind>().is_err());
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
 }
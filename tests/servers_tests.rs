@@ -1,13 +1,284 @@
 use GServerManager::server::server::Server;
-use GServerManager::server::servers::Servers;
+use GServerManager::server::servers::{Servers, ServerEdit};
+use GServerManager::server::blue_green::{BlueGreen, Color};
+use GServerManager::server::snapshots::{self, MAX_SNAPSHOTS};
+use GServerManager::server::bulk::{run_parallel, Status};
+use GServerManager::error::GsmError;
+use GServerManager::utils::output::OutputFormat;
+use std::time::{Duration, Instant};
 use std::path::PathBuf;
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    fn test_server(name: &str, port: u32) -> Server {
+        let path = PathBuf::from("tests/test-servers").join(name);
+        Server::new(name, path, port)
+    }
+
+    fn test_servers() -> Servers {
+        Servers::new(vec![test_server("server-1", 8001), test_server("server-2", 8002)])
+    }
+
+    #[test]
+    fn test_edit_rejects_taken_port() {
+        let mut servers = test_servers();
+        let edit = ServerEdit { port: Some(8002), ..Default::default() };
+
+        assert!(matches!(servers.edit_server("server-1", edit), Err(GsmError::PortInUse(8002))));
+        assert_eq!(servers.fetch_server("server-1").unwrap().port, 8001);
+    }
+
+    #[test]
+    fn test_edit_unknown_server() {
+        let mut servers = test_servers();
+        let edit = ServerEdit { workers: Some(2), ..Default::default() };
+
+        let error = servers.edit_server("server-3", edit).unwrap_err();
+        assert!(matches!(&error, GsmError::NotFound(name) if name == "server-3"));
+        assert_eq!(error.to_string(), "Server [server-3] not found");
+    }
+
+    #[test]
+    fn test_blue_green_ports_are_reserved() {
+        let mut servers = test_servers();
+        servers.servers[0].blue_green = Some(BlueGreen {
+            green_port: 9001,
+            active: Color::Green,
+            upstream: None,
+            proxy_reload: None,
+        });
+        assert_eq!(servers.servers[0].serving_port(), 9001);
+        assert!(servers.servers[0].pid_file().ends_with("server-1-green.pid"));
+
+        let edit = ServerEdit { port: Some(9001), ..Default::default() };
+        assert!(servers.edit_server("server-2", edit).is_err());
+        assert!(servers.enable_blue_green("server-2", 8001, None, None).is_err());
+        assert!(servers.enable_blue_green("server-2", 8002, None, None).is_err());
+        assert!(servers.fetch_server("server-2").unwrap().blue_green.is_none());
+    }
+
+    #[test]
+    fn test_select_servers() {
+        let mut servers = test_servers();
+        servers.servers.push(test_server("worker-1", 8003));
+        servers.servers[0].tags = vec![String::from("api"), String::from("prod")];
+        servers.servers[1].tags = vec![String::from("api")];
+
+        let tags = |tags: &[&str]| tags.iter().map(|tag| tag.to_string()).collect::<Vec<_>>();
+        assert_eq!(servers.select(Some("server-2"), &[], false).unwrap(), vec!["server-2"]);
+        assert_eq!(servers.select(Some("server-*"), &[], false).unwrap(), vec!["server-1", "server-2"]);
+        assert_eq!(servers.select(None, &tags(&["api"]), false).unwrap(), vec!["server-1", "server-2"]);
+        assert_eq!(servers.select(None, &tags(&["api", "prod"]), false).unwrap(), vec!["server-1"]);
+        assert_eq!(servers.select(Some("*-2"), &tags(&["api"]), false).unwrap(), vec!["server-2"]);
+        assert_eq!(servers.select(None, &[], true).unwrap().len(), 3);
+
+        assert!(matches!(servers.select(Some("server-3"), &[], false), Err(GsmError::NotFound(_))));
+        assert!(servers.select(Some("db-*"), &[], false).is_err());
+        assert!(servers.select(None, &tags(&["staging"]), false).is_err());
+        assert!(servers.select(None, &[], false).is_err());
+    }
+
+    #[test]
+    fn test_dependency_order() {
+        let mut servers = test_servers();
+        servers.servers.push(test_server("redis-server", 6379));
+        servers.servers[0].depends_on = vec![String::from("server-2")];
+        servers.servers[1].depends_on = vec![String::from("redis-server")];
+
+        let names = |names: &[&str]| names.iter().map(|name| name.to_string()).collect::<Vec<_>>();
+        assert_eq!(servers.dependency_order(&names(&["server-1"])).unwrap(), vec!["redis-server", "server-2", "server-1"]);
+        assert_eq!(servers.dependency_order(&names(&["redis-server", "server-1", "server-2"])).unwrap(), vec!["redis-server", "server-2", "server-1"]);
+
+        let edit = ServerEdit { depends_on: names(&["server-1"]), ..Default::default() };
+        let error = servers.edit_server("redis-server", edit).unwrap_err().to_string();
+        assert_eq!(error, "Dependency cycle: redis-server -> server-1 -> server-2 -> redis-server");
+        assert!(servers.fetch_server("redis-server").unwrap().depends_on.is_empty());
+
+        let edit = ServerEdit { depends_on: names(&["server-3"]), ..Default::default() };
+        assert!(matches!(servers.edit_server("server-2", edit), Err(GsmError::Dependency(_))));
+        assert!(matches!(servers.remove_server("redis-server"), Err(GsmError::Dependency(_))));
+        assert_eq!(servers.num_servers(), 3);
+
+        servers.servers[2].depends_on = vec![String::from("server-1")];
+        assert!(servers.dependency_order(&names(&["server-1"])).is_err());
+    }
+
+    #[test]
+    fn test_stopping_includes_running_dependents() {
+        let mut servers = test_servers();
+        servers.servers.push(test_server("redis-server", 6379));
+        servers.servers.push(test_server("server-3", 8003));
+        servers.servers[0].depends_on = vec![String::from("server-2")];
+        servers.servers[1].depends_on = vec![String::from("redis-server")];
+        servers.servers[3].depends_on = vec![String::from("redis-server")];
+        servers.servers[0].running = true;
+        servers.servers[1].running = true;
+
+        let names = |names: &[&str]| names.iter().map(|name| name.to_string()).collect::<Vec<_>>();
+        // server-3 isn't running, so it's left alone
+        assert_eq!(servers.with_running_dependents(&names(&["redis-server"])), vec!["redis-server", "server-2", "server-1"]);
+        assert_eq!(servers.with_running_dependents(&names(&["server-1"])), vec!["server-1"]);
+    }
+
     #[test]
-    fn test_servers_functionality() {
-        // need updated tests
+    fn test_run_parallel() {
+        let mut servers = (1..=6).map(|i| test_server(&format!("server-{}", i), 8000 + i)).collect::<Vec<_>>();
+
+        let started = Instant::now();
+        let results = run_parallel(servers.iter_mut(), 3, |server| {
+            // Later servers finish first, results must still follow the input order
+            std::thread::sleep(Duration::from_millis(50 * (7 - server.port as u64 % 10)));
+            server.running = true;
+            if server.name == "server-4" {
+                return Err(String::from("port in use"));
+            }
+            Ok(())
+        });
+        assert!(started.elapsed() < Duration::from_millis(900));
+
+        let names = results.iter().map(|result| result.name.as_str()).collect::<Vec<_>>();
+        assert_eq!(names, vec!["server-1", "server-2", "server-3", "server-4", "server-5", "server-6"]);
+        assert_eq!(results[3].status, Status::Failed);
+        assert_eq!(results[3].message, "port in use");
+        assert!(results.iter().filter(|result| result.name != "server-4").all(|result| result.status == Status::Ok));
+        assert!(servers.iter().all(|server| server.running));
+    }
+
+    #[test]
+    fn test_list_all_as_json() {
+        let mut servers = test_servers();
+        servers.servers[0].env.insert(String::from("SECRET_KEY"), String::from("hunter2"));
+        servers.servers[0].tags = vec![String::from("api")];
+
+        let json = serde_json::to_value(servers.list_all()).unwrap();
+        assert_eq!(json.as_array().unwrap().len(), 2);
+        assert_eq!(json[0]["name"], "server-1");
+        assert_eq!(json[0]["port"], 8001);
+        assert_eq!(json[0]["running"], false);
+        assert_eq!(json[0]["env"]["SECRET_KEY"], "****");
+        assert_eq!(json[0]["tags"][0], "api");
+        assert!(json[1]["commit"].is_null());
+
+        assert_eq!("json".parse::<OutputFormat>().unwrap(), OutputFormat::Json);
+        assert!("yaml".parse::<OutputFormat>().is_err());
+    }
+
+    #[test]
+    fn test_restore_falls_back_to_last_good_copy() {
+        let dir = std::env::temp_dir().join("gsm_test_restore");
+        let _ = std::fs::remove_dir_all(&dir);
+        let path = dir.join("servers.json");
+
+        let mut servers = test_servers();
+        servers.backup_to(&path).unwrap();
+        assert!(!dir.join("servers.json.tmp").exists());
+        assert!(!dir.join("servers.json.bak").exists());
+        servers.servers[1].port = 8003;
+        servers.backup_to(&path).unwrap();
+        drop(servers);
+
+        // A file damaged outside the program falls back to the state before the last write
+        std::fs::write(&path, "[{\"name\": \"serv").unwrap();
+        let mut servers = Servers::new(Vec::new());
+        servers.restore_from(&path).unwrap();
+        assert_eq!(servers.num_servers(), 2);
+        assert_eq!(servers.fetch_server("server-2").unwrap().port, 8002);
+
+        // and a corrupt state file never replaces the last good copy
+        servers.backup_to(&path).unwrap();
+        servers.restore_from(&dir.join("servers.json.bak")).unwrap();
+        assert_eq!(servers.fetch_server("server-2").unwrap().port, 8002);
+
+        std::fs::write(&path, "").unwrap();
+        std::fs::write(dir.join("servers.json.bak"), "").unwrap();
+        assert!(matches!(servers.restore_from(&path), Err(GsmError::Backup(_))));
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_second_writer_is_rejected() {
+        let dir = std::env::temp_dir().join("gsm_test_second_writer");
+        let _ = std::fs::remove_dir_all(&dir);
+        let path = dir.join("servers.json");
+
+        let first = test_servers();
+        first.claim(&path).unwrap();
+        first.backup_to(&path).unwrap();
+
+        // A second instance can read the state but not write it
+        let mut second = Servers::new(Vec::new());
+        assert!(matches!(second.claim(&path), Err(GsmError::InvalidState(_))));
+        second.restore_from(&path).unwrap();
+        assert_eq!(second.num_servers(), 2);
+        second.servers.remove(0);
+        assert!(second.backup_to(&path).is_err());
+        first.backup_to(&path).unwrap();
+
+        drop(first);
+        second.backup_to(&path).unwrap();
+        let mut reloaded = Servers::new(Vec::new());
+        reloaded.restore_from(&path).unwrap();
+        assert_eq!(reloaded.num_servers(), 1);
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_snapshots() {
+        let dir = std::env::temp_dir().join("gsm_test_snapshots");
+        let _ = std::fs::remove_dir_all(&dir);
+        let path = dir.join("servers.json");
+        let snapshots_dir = dir.join("snapshots");
+
+        let mut servers = test_servers();
+        servers.backup_to(&path).unwrap();
+        servers.servers[0].port = 8003;
+        servers.servers.remove(1);
+        servers.servers[0].running = true;
+        servers.backup_to(&path).unwrap();
+
+        let list = snapshots::list(&snapshots_dir).unwrap();
+        assert_eq!(list.iter().map(|snapshot| snapshot.id).collect::<Vec<_>>(), vec![1, 2]);
+        assert_eq!(list[0].servers, vec!["server-1", "server-2"]);
+
+        let snapshot = snapshots::load(&snapshots_dir, 1).unwrap();
+        assert_eq!(snapshots::diff(&servers.servers, &snapshot), vec!["~ server-1 port: 8003 -> 8001", "+ server-2"]);
+        servers.apply_snapshot(snapshot).unwrap();
+        let restored = servers.fetch_server("server-1").unwrap();
+        assert_eq!(restored.port, 8001);
+        assert!(restored.running);
+        // Still serving the port it was started with until it is restarted
+        assert_eq!(restored.running_address, Some((restored.bind.clone(), 8003)));
+        assert!(servers.list_all()[0].restart_required);
+        assert!(!servers.fetch_server("server-2").unwrap().running);
+
+        // A running server the snapshot doesn't have is never dropped
+        servers.servers.push(Server { running: true, ..test_server("server-3", 8005) });
+        let snapshot = snapshots::load(&snapshots_dir, 1).unwrap();
+        assert!(matches!(servers.apply_snapshot(snapshot), Err(GsmError::InvalidState(_))));
+        assert!(servers.fetch_server("server-3").is_some());
+        servers.servers.pop();
+        assert!(matches!(snapshots::load(&snapshots_dir, 3), Err(GsmError::InvalidArgument(_))));
+
+        // Backups that only change the running state don't add snapshots
+        servers.backup_to(&path).unwrap();
+        servers.servers[0].running = false;
+        servers.backup_to(&path).unwrap();
+        servers.backup_to(&path).unwrap();
+        assert_eq!(snapshots::list(&snapshots_dir).unwrap().len(), 3);
+
+        // Only the latest snapshots are kept
+        for workers in 0..MAX_SNAPSHOTS {
+            servers.servers[0].workers = workers as u32;
+            servers.backup_to(&path).unwrap();
+        }
+        let list = snapshots::list(&snapshots_dir).unwrap();
+        assert_eq!(list.len(), MAX_SNAPSHOTS);
+        assert_eq!(list[0].id, 4);
+
+        std::fs::remove_dir_all(dir).unwrap();
     }
-}
\ No newline at end of file
+}
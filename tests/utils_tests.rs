@@ -0,0 +1,201 @@
+use GServerManager::server::server::Server;
+use GServerManager::utils::build::{BuildStep, default_build_steps, run_build_steps};
+use GServerManager::utils::detect::{detect_framework, detect_app, parse_app};
+use GServerManager::utils::deps::{changed_dependency_files, install_commands};
+use GServerManager::utils::health::{check_http, wait_until_healthy};
+use GServerManager::utils::output::Outcome;
+use GServerManager::utils::state::{resolve, migrate, STATE_DIR_ENV};
+use std::io::{Read, Write};
+use std::net::TcpListener;
+use std::time::{Duration, Instant};
+use std::path::{Path, PathBuf};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_changed_dependency_files() {
+        let diff = "app.py\nrequirements.txt\ndocs/pyproject.toml";
+        assert_eq!(changed_dependency_files(diff), vec!["requirements.txt"]);
+        assert!(changed_dependency_files("main.py\nsrc/lib.cpp").is_empty());
+    }
+
+    #[test]
+    fn test_install_commands() {
+        let path = PathBuf::from("tests/test-servers/server-1");
+
+        let commands = install_commands(&["requirements.txt"], &path, Some("/venv/bin/pip"));
+        assert_eq!(commands, vec![vec!["/venv/bin/pip", "install", "-r", "requirements.txt"]]);
+
+        let commands = install_commands(&["pyproject.toml", "poetry.lock"], &path, Some("pip"));
+        assert_eq!(commands, vec![vec!["poetry", "install", "--no-root"]]);
+
+        let commands = install_commands(&["pyproject.toml", "Pipfile.lock"], &path, Some("pip"));
+        assert_eq!(commands, vec![vec!["pipenv", "sync"], vec!["pip", "install", "."]]);
+
+        // Without a virtualenv pip would install into the system Python
+        let commands = install_commands(&["requirements.txt", "pyproject.toml", "Pipfile.lock"], &path, None);
+        assert_eq!(commands, vec![vec!["pipenv", "sync"]]);
+    }
+
+    fn build_step(command: &str, patterns: &[&str], timeout: Option<u64>) -> BuildStep {
+        BuildStep {
+            command: command.to_string(),
+            dir: None,
+            env: Default::default(),
+            timeout,
+            patterns: patterns.iter().map(|p| p.to_string()).collect(),
+        }
+    }
+
+    fn build_server(dir: &Path) -> Server {
+        Server::new("build-test", dir.to_path_buf(), 8000)
+    }
+
+    #[test]
+    fn test_build_step_patterns() {
+        let step = build_step("make", &["src/**/*.cpp", "CMakeLists.txt"], None);
+        assert!(step.is_triggered(&["src/core/main.cpp"]).unwrap());
+        assert!(step.is_triggered(&["CMakeLists.txt"]).unwrap());
+        assert!(!step.is_triggered(&["app.py", "lib/CMakeLists.txt"]).unwrap());
+        assert!(build_step("make", &[], None).is_triggered(&[]).unwrap());
+
+        assert!(default_build_steps(&PathBuf::from("tests/test-servers/server-1")).is_empty());
+    }
+
+    #[test]
+    fn test_build_steps_failure_and_log() {
+        let dir = std::env::temp_dir().join("gsm_test_build_steps");
+        std::fs::create_dir_all(&dir).unwrap();
+        let server = build_server(&dir);
+        let log_file = server.build_log();
+        let _ = std::fs::remove_file(&log_file);
+
+        let steps = vec![
+            build_step("echo first", &[], None),
+            build_step("echo skipped", &["*.cpp"], None),
+            build_step("echo broken >&2; exit 3", &[], None),
+            build_step("echo never", &[], None),
+        ];
+        assert!(run_build_steps(&server, &steps, Some(&["app.py"]), &log_file).is_err());
+
+        let log = std::fs::read_to_string(&log_file).unwrap();
+        assert!(log.contains("first") && log.contains("broken"));
+        assert!(!log.contains("echo skipped") && !log.contains("never"));
+
+        let steps = vec![build_step("sleep 5", &[], Some(0))];
+        let error = run_build_steps(&server, &steps, None, &log_file).unwrap_err();
+        assert!(error.contains("timed out"));
+
+        // Commands started by a compound command are killed with the shell
+        let pid_file = dir.join("sleep.pid");
+        let steps = vec![build_step(&format!("sleep 30 & echo $! > {}; sleep 30", pid_file.display()), &[], Some(1))];
+        assert!(run_build_steps(&server, &steps, None, &log_file).unwrap_err().contains("timed out"));
+        let pid = std::fs::read_to_string(&pid_file).unwrap();
+        let alive = || std::fs::read_to_string(format!("/proc/{}/stat", pid.trim()))
+            .is_ok_and(|stat| !stat.contains(") Z "));
+        let started = Instant::now();
+        while alive() && started.elapsed() < Duration::from_secs(2) {
+            std::thread::sleep(Duration::from_millis(50));
+        }
+        assert!(!alive());
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_health_check() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port() as u32;
+        std::thread::spawn(move || {
+            for (index, stream) in listener.incoming().enumerate() {
+                let mut stream = stream.unwrap();
+                let mut request = [0; 512];
+                let _ = stream.read(&mut request);
+                let status = if index == 0 { "503 Service Unavailable" } else { "200 OK" };
+                let _ = stream.write_all(format!("HTTP/1.0 {}\r\n\r\n", status).as_bytes());
+            }
+        });
+
+        assert_eq!(check_http("0.0.0.0", port, "/").unwrap(), 503);
+        assert!(wait_until_healthy("127.0.0.1", port, "/", Duration::from_secs(5)).is_ok());
+    }
+
+    #[test]
+    fn test_detect_framework() {
+        assert_eq!(detect_framework(Path::new("tests/test-servers/server-1")), Some("flask"));
+        assert_eq!(detect_framework(Path::new("tests/test-servers/server-3")), Some("flask"));
+        assert_eq!(detect_framework(Path::new("tests/does-not-exist")), None);
+    }
+
+    #[test]
+    fn test_detect_app() {
+        assert_eq!(detect_app(Path::new("tests/test-servers/server-1"), "flask"), Some(String::from("main:app")));
+        assert_eq!(detect_app(Path::new("tests/test-servers/server-3"), "flask"), Some(String::from("server:app")));
+
+        let dir = std::env::temp_dir().join("gsm_test_detect_app");
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(dir.join("api")).unwrap();
+        std::fs::write(dir.join("pyproject.toml"), "[project]\nname = \"api\"\n\n[project.scripts]\nserve = \"api.web:run\"\n").unwrap();
+        std::fs::write(dir.join("api/web.py"), "from fastapi import FastAPI\n\nservice: FastAPI = FastAPI()\n").unwrap();
+        std::fs::write(dir.join("main.py"), "def create_app():\n    pass\n").unwrap();
+        assert_eq!(detect_framework(&dir), Some("fastapi"));
+        assert_eq!(detect_app(&dir, "fastapi"), Some(String::from("api.web:service")));
+        assert_eq!(detect_app(&dir, "flask"), Some(String::from("main:create_app()")));
+
+        let site = std::env::temp_dir().join("gsm_test_detect_django");
+        let _ = std::fs::remove_dir_all(&site);
+        std::fs::create_dir_all(site.join("mysite")).unwrap();
+        std::fs::write(site.join("manage.py"), "os.environ.setdefault('DJANGO_SETTINGS_MODULE', 'mysite.settings')\n").unwrap();
+        std::fs::write(site.join("mysite/wsgi.py"), "application = get_wsgi_application()\n").unwrap();
+        assert_eq!(detect_framework(&site), Some("django"));
+        assert_eq!(detect_app(&site, "django"), Some(String::from("mysite.wsgi:application")));
+
+        assert!(parse_app("mysite.wsgi:application").is_ok());
+        assert!(parse_app("main:create_app()").is_ok());
+        assert!(parse_app("main").is_err());
+        assert!(parse_app("my-site:app").is_err());
+    }
+
+    #[test]
+    fn test_outcome_json() {
+        let outcome = Outcome { command: String::from("edit"), name: Some(String::from("api")), ok: true, error: None };
+        assert_eq!(serde_json::to_string(&outcome).unwrap(), r#"{"command":"edit","name":"api","ok":true}"#);
+
+        let outcome = Outcome { ok: false, error: Some(String::from("Server [api] not found")), ..outcome };
+        assert_eq!(serde_json::to_value(&outcome).unwrap()["error"], "Server [api] not found");
+    }
+
+    #[test]
+    fn test_state_dir() {
+        assert_eq!(resolve(Some(Path::new("/srv/gsm"))), PathBuf::from("/srv/gsm"));
+        assert_eq!(resolve(Some(Path::new("state"))), std::env::current_dir().unwrap().join("state"));
+
+        std::env::set_var(STATE_DIR_ENV, "/var/lib/gsm");
+        assert_eq!(resolve(None), PathBuf::from("/var/lib/gsm"));
+        assert_eq!(resolve(Some(Path::new("/srv/gsm"))), PathBuf::from("/srv/gsm"));
+        std::env::remove_var(STATE_DIR_ENV);
+        assert_ne!(resolve(None), PathBuf::from("/var/lib/gsm"));
+    }
+
+    #[test]
+    fn test_migrate_legacy_backups() {
+        let legacy = std::env::temp_dir().join("gsm_test_migrate_backups");
+        let dir = std::env::temp_dir().join("gsm_test_migrate_state");
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&legacy).unwrap();
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(legacy.join("servers_backup.json"), "[]").unwrap();
+        std::fs::write(legacy.join("history.txt"), "list").unwrap();
+        std::fs::write(dir.join("history.txt"), "start").unwrap();
+
+        migrate(&legacy, &dir).unwrap();
+        assert_eq!(std::fs::read_to_string(dir.join("servers.json")).unwrap(), "[]");
+        assert!(!legacy.join("servers_backup.json").exists());
+
+        // The state directory's own history is kept
+        assert_eq!(std::fs::read_to_string(dir.join("history.txt")).unwrap(), "start");
+        assert!(legacy.join("history.txt").exists());
+    }
+}